@@ -0,0 +1,171 @@
+use std::hint::black_box;
+use std::sync::Arc;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use ray_tracing::camera::{Camera, PerspectiveCamera};
+use ray_tracing::color::Color;
+use ray_tracing::hit_record::HitRecord;
+use ray_tracing::material::{DiffuseLight, Lambertian, Metal};
+use ray_tracing::objects::Sphere;
+use ray_tracing::ray::Ray;
+use ray_tracing::scene::Scene;
+use ray_tracing::vec3::Vec3;
+use ray_tracing::{calculate_image, Hittable, Image, RenderConfig};
+
+fn gray() -> Box<Lambertian> {
+    Box::new(Lambertian::new(Color::from_frac(0.5, 0.5, 0.5).unwrap()))
+}
+
+/// Small image of a few spheres on the ground lit by a sphere light, rendered with a fixed seed,
+/// so that every run traces the same paths.
+fn render_benches(c: &mut Criterion) {
+    let image = Image::new(64, 1.5, 1.);
+    let camera: Arc<dyn Camera> = Arc::new(PerspectiveCamera::look_at(
+        Vec3::new(0., 1., 3.),
+        Vec3::new(0., 0.5, -1.),
+        Vec3::new(0., 1., 0.),
+        40.,
+        image.aspect_ratio(),
+    ));
+    let mut scene = Scene::new();
+    scene.add(Sphere::at(0., -1000., -1., 1000., gray()));
+    scene.add(Sphere::at(-1.1, 0.5, -1., 0.5, gray()));
+    scene.add(Sphere::at(
+        0.,
+        0.5,
+        -1.,
+        0.5,
+        Box::new(Metal::fuzzy(Color::from_frac(0.8, 0.6, 0.2).unwrap(), 0.3)),
+    ));
+    scene.add(Sphere::at(
+        1.1,
+        0.5,
+        -1.,
+        0.5,
+        Box::new(Metal::shiny(Color::from_frac(0.9, 0.9, 0.9).unwrap())),
+    ));
+    scene.add(Sphere::at(
+        0.,
+        3.,
+        0.,
+        0.5,
+        Box::new(DiffuseLight::new(Color::white(), 4.)),
+    ));
+    let scene = Arc::new(scene);
+    let mut config = RenderConfig::new();
+    config.seed = Some(1);
+    config.samples = 4;
+
+    let mut group = c.benchmark_group("render");
+    // A render takes milliseconds, fewer samples keep the benchmark short
+    group.sample_size(10);
+    group.bench_function("calculate_image 64x42", |b| {
+        b.iter(|| calculate_image(&camera, &image, &config, &scene).unwrap())
+    });
+    group.finish();
+}
+
+fn hit_benches(c: &mut Criterion) {
+    let sphere = Sphere::new(Vec3::new(0., 0., -1.), 0.5, gray());
+    // Half of the rays hit the sphere, the other half misses it
+    let rays: Vec<Ray> = (0..1000)
+        .map(|i| Ray::new(Vec3::zero(), Vec3::new(i as f64 / 1000. - 0.5, 0., -1.)))
+        .collect();
+
+    c.bench_function("Sphere::hit x1000", |b| {
+        b.iter(|| {
+            let mut rec = HitRecord::new();
+            for ray in &rays {
+                black_box(sphere.hit(black_box(ray), 0.001, f64::MAX, &mut rec));
+            }
+        })
+    });
+
+    // Nearest hit search either creates a record for every tested object or reuses one
+    c.bench_function("HitRecord::new x1000", |b| {
+        b.iter(|| {
+            for ray in &rays {
+                let mut rec = HitRecord::new();
+                black_box(sphere.hit(black_box(ray), 0.001, f64::MAX, &mut rec));
+                black_box(&rec);
+            }
+        })
+    });
+    c.bench_function("HitRecord::reset x1000", |b| {
+        b.iter(|| {
+            let mut rec = HitRecord::new();
+            for ray in &rays {
+                rec.reset();
+                black_box(sphere.hit(black_box(ray), 0.001, f64::MAX, &mut rec));
+                black_box(&rec);
+            }
+        })
+    });
+
+    #[cfg(feature = "batch-hit")]
+    nearest_sphere_benches(c);
+}
+
+/// Nearest hit of 484 small spheres on a grid (as in the random scene) found by testing the
+/// spheres one by one and by `SphereBatch`.
+#[cfg(feature = "batch-hit")]
+fn nearest_sphere_benches(c: &mut Criterion) {
+    use ray_tracing::sphere_batch::SphereBatch;
+
+    let spheres: Vec<Sphere> = (0..484)
+        .map(|i| {
+            let (a, b) = ((i % 22) as f64 - 11., (i / 22) as f64 - 11.);
+            Sphere::new(Vec3::new(a + 0.45, 0.2, b + 0.45), 0.2, gray())
+        })
+        .collect();
+    let batch = SphereBatch::new(&spheres);
+    // Rays from the camera of the random scene towards the grid
+    let origin = Vec3::new(13., 2., 3.);
+    let rays: Vec<Ray> = (0..1000)
+        .map(|i| {
+            let target = Vec3::new((i % 40) as f64 / 2. - 10., 0., (i / 40) as f64 - 12.);
+            Ray::new(origin, target - origin)
+        })
+        .collect();
+
+    c.bench_function("nearest of 484 scalar", |b| {
+        b.iter(|| {
+            let mut rec = HitRecord::new();
+            for ray in &rays {
+                let mut closest_t = f64::MAX;
+                for sphere in &spheres {
+                    if sphere.hit(black_box(ray), 0.001, closest_t, &mut rec) {
+                        closest_t = rec.t();
+                    }
+                }
+                black_box(closest_t);
+            }
+        })
+    });
+    c.bench_function("nearest of 484 batch", |b| {
+        b.iter(|| {
+            let mut rec = HitRecord::new();
+            for ray in &rays {
+                black_box(batch.hit(&spheres, black_box(ray), 0.001, f64::MAX, &mut rec));
+            }
+        })
+    });
+}
+
+fn vec3_benches(c: &mut Criterion) {
+    let a = Vec3::new(1., 2., 3.);
+    let b = Vec3::new(-4., 0.5, 2.);
+    c.bench_function("Vec3::dot", |bencher| {
+        bencher.iter(|| Vec3::dot(black_box(a), black_box(b)))
+    });
+    c.bench_function("Vec3::cross", |bencher| {
+        bencher.iter(|| Vec3::cross(black_box(a), black_box(b)))
+    });
+    c.bench_function("Vec3::normalize", |bencher| {
+        bencher.iter(|| *black_box(a).normalize())
+    });
+}
+
+criterion_group!(benches, render_benches, hit_benches, vec3_benches);
+criterion_main!(benches);
@@ -0,0 +1,101 @@
+use crate::mat4::Mat4;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+use crate::vec3::Vec3 as Point;
+
+/// Axis-aligned bounding box, the smallest box with faces parallel to the coordinate planes that
+/// contains the whole object.
+///
+/// More thorough explanation can be found at:
+/// https://raytracing.github.io/books/RayTracingTheNextWeek.html#boundingvolumehierarchies/axis-alignedboundingboxes(aabbs)
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb {
+    min: Point,
+    max: Point,
+}
+
+impl Aabb {
+    /// Box with opposite corners `a` and `b` (in any order).
+    pub fn new(a: Point, b: Point) -> Aabb {
+        Aabb {
+            min: Vec3::component_min(a, b),
+            max: Vec3::component_max(a, b),
+        }
+    }
+
+    /// Corner with the smallest coordinates.
+    pub fn min(&self) -> Point {
+        self.min
+    }
+
+    /// Corner with the largest coordinates.
+    pub fn max(&self) -> Point {
+        self.max
+    }
+
+    pub fn center(&self) -> Point {
+        0.5 * &(self.min + self.max)
+    }
+
+    /// Diagonal of the box, from `min` to `max`.
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    /// Returns `true` if the `point` is inside of the box (or on its faces).
+    pub fn contains(&self, point: Point) -> bool {
+        (self.min.x()..=self.max.x()).contains(&point.x())
+            && (self.min.y()..=self.max.y()).contains(&point.y())
+            && (self.min.z()..=self.max.z()).contains(&point.z())
+    }
+
+    /// Returns `true` if the ray passes through the box on interval (t_min, t_max). Uses the slab
+    /// method, the interval is narrowed by the intersections with pairs of parallel faces.
+    ///
+    /// More thorough explanation can be found at:
+    /// https://raytracing.github.io/books/RayTracingTheNextWeek.html#boundingvolumehierarchies/rayintersectionwithanaabb
+    pub fn hit(&self, ray: &Ray, mut t_min: f64, mut t_max: f64) -> bool {
+        let (origin, direction) = (ray.origin(), ray.direction());
+        for (o, d, min, max) in [
+            (origin.x(), direction.x(), self.min.x(), self.max.x()),
+            (origin.y(), direction.y(), self.min.y(), self.max.y()),
+            (origin.z(), direction.z(), self.min.z(), self.max.z()),
+        ] {
+            let inverse = 1. / d;
+            let (mut t0, mut t1) = ((min - o) * inverse, (max - o) * inverse);
+            if inverse < 0. {
+                core::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+            if t_max < t_min {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Smallest box containing both boxes.
+    pub fn surrounding(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: Vec3::component_min(self.min, other.min),
+            max: Vec3::component_max(self.max, other.max),
+        }
+    }
+
+    /// Box containing this box transformed by `matrix`, i.e. the box around its transformed
+    /// corners (it is larger than the box of the object itself, e.g. for rotated spheres).
+    pub fn transformed(&self, matrix: &Mat4) -> Aabb {
+        let corner = |i: u8| {
+            let pick = |bit: u8, min: f64, max: f64| if i & bit == 0 { min } else { max };
+            matrix.transform_point(Point::new(
+                pick(1, self.min.x(), self.max.x()),
+                pick(2, self.min.y(), self.max.y()),
+                pick(4, self.min.z(), self.max.z()),
+            ))
+        };
+        (1..8).fold(Aabb::new(corner(0), corner(0)), |bbox, i| {
+            bbox.surrounding(&Aabb::new(corner(i), corner(i)))
+        })
+    }
+}
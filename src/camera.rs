@@ -1,43 +1,293 @@
+use crate::aabb::Aabb;
+use crate::math;
 use crate::ray::Ray;
 use crate::vec3::Vec3;
 use crate::vec3::Vec3 as Point;
 
+/// Projection of the scene to the image, it generates a ray for every point of the image.
+pub trait Camera: Send + Sync {
+    /// Creates a new ray going through the point of the image given by `u` (from the left edge) and
+    /// `v` (from the bottom edge), both from 0 to 1.
+    fn calculate_ray(&self, u: f64, v: f64) -> Ray;
+
+    /// Ratio of the width of the image to its height, for which the camera is set up.
+    fn aspect_ratio(&self) -> f64;
+}
+
+/// Pinhole camera with perspective projection.
+///
 /// Image sensor (imager) parameters:
-/// * `focal length` is a distance between projection plane to projection point (origin),
-/// * `origin` and `lower_left_corner` together with `focal_length` determine a spacial orientation of
-/// a virtual sensor.
-pub struct Sensor {
+/// * `origin` is the projection point, all rays start there,
+/// * `horizontal` and `vertical` span the virtual sensor (viewport) in front of the origin,
+/// * `lower_left_direction` goes from the origin to the lower left corner of the viewport.
+///
+/// All of them are derived from one orthonormal basis of the camera (see `from_basis`). The
+/// viewport is scaled to distance 1 from the origin, only its angular size matters, so ray
+/// directions have similar lengths with any focal length.
+pub struct PerspectiveCamera {
     origin: Point,
     horizontal: Vec3,
     vertical: Vec3,
-    lower_left_corner: Point,
+    lower_left_direction: Vec3,
 }
 
-impl Sensor {
-    pub fn new(height: f64, aspect_ratio: f64, focal_length: f64) -> Sensor {
-        let origin = Point::zero();
+impl PerspectiveCamera {
+    /// Creates a camera at the origin of the scene looking along -Z, `focal_length` is the distance
+    /// of the sensor from the origin.
+    pub fn new(height: f64, aspect_ratio: f64, focal_length: f64) -> PerspectiveCamera {
+        PerspectiveCamera::from_basis(
+            Point::zero(),
+            [
+                Vec3::new(1., 0., 0.),
+                Vec3::new(0., 1., 0.),
+                Vec3::new(0., 0., 1.),
+            ],
+            aspect_ratio * height,
+            height,
+            focal_length,
+        )
+    }
+
+    /// Creates a sensor placed at `lookfrom` pointing towards `lookat`. `vup` is a vector determining
+    /// the up direction of the picture (roll of the camera) and `vfov` is a vertical field of view
+    /// in degrees.
+    pub fn look_at(
+        lookfrom: Point,
+        lookat: Point,
+        vup: Vec3,
+        vfov: f64,
+        aspect_ratio: f64,
+    ) -> PerspectiveCamera {
+        let height = 2.0 * math::tan(vfov.to_radians() / 2.0);
         let width = aspect_ratio * height;
-        let horizontal = Vec3::new(width, 0., 0.);
-        let vertical = Vec3::new(0., height, 0.);
 
-        Sensor {
+        // Orthonormal basis of the camera, `w` points away from the scene
+        let w = (lookfrom - lookat).unit();
+        let u = Vec3::cross(vup, w).unit();
+        let v = Vec3::cross(w, u);
+
+        PerspectiveCamera::from_basis(lookfrom, [u, v, w], width, height, 1.0)
+    }
+
+    /// Creates a camera looking in `direction` at the center of `bbox` (e.g. the bounding box of
+    /// the scene) from the distance at which the whole box is in view. `vfov` is a vertical field of
+    /// view in degrees. The up direction of the picture is +Y (+Z when looking along the Y axis).
+    ///
+    /// The box is framed by its bounding sphere, so there is some margin around it. Returns `None`
+    /// if `direction` is zero.
+    pub fn fit_to_bounds(
+        bbox: &Aabb,
+        vfov: f64,
+        aspect_ratio: f64,
+        direction: Vec3,
+    ) -> Option<PerspectiveCamera> {
+        if direction.near_zero() {
+            return None;
+        }
+        let direction = direction.unit();
+        let radius = bbox.size().length() / 2.;
+
+        // The sphere must fit into the narrower of the vertical and horizontal field of view
+        let half_vfov = vfov.to_radians() / 2.;
+        let half_hfov = math::atan(aspect_ratio * math::tan(half_vfov));
+        let distance = radius / math::sin(half_vfov.min(half_hfov));
+
+        let lookat = bbox.center();
+        let lookfrom = lookat - distance * &direction;
+        let vup = if Vec3::cross(direction, Vec3::new(0., 1., 0.)).near_zero() {
+            Vec3::new(0., 0., 1.)
+        } else {
+            Vec3::new(0., 1., 0.)
+        };
+        Some(PerspectiveCamera::look_at(
+            lookfrom,
+            lookat,
+            vup,
+            vfov,
+            aspect_ratio,
+        ))
+    }
+
+    /// Creates a camera at `origin` with orthonormal basis `[u, v, w]`: `u` points to the right of
+    /// the image, `v` up and `w` backwards (away from the scene). The sensor of size `width` x
+    /// `height` is centered on the view axis at `distance` in front of the origin.
+    ///
+    /// The sensor is stored scaled to distance 1 (the same field of view), very long or very short
+    /// focal lengths would otherwise give ray directions far from unit length, e.g. with a huge
+    /// component along the view axis and tiny offsets of the pixels across it.
+    fn from_basis(
+        origin: Point,
+        [u, v, w]: [Vec3; 3],
+        width: f64,
+        height: f64,
+        distance: f64,
+    ) -> PerspectiveCamera {
+        let horizontal = (width / distance) * &u;
+        let vertical = (height / distance) * &v;
+        let center = -w;
+
+        PerspectiveCamera {
             origin,
             horizontal,
             vertical,
-            lower_left_corner: origin
-                - horizontal / 2.0
-                - vertical / 2.0
-                - Vec3::new(0., 0., focal_length),
+            lower_left_direction: center - horizontal / 2.0 - vertical / 2.0,
         }
     }
+}
 
+impl Camera for PerspectiveCamera {
     /// Creates a new ray going from the origin through the virtual viewport pixel, which is given
-    /// by offset vectors `u` and `v`.
-    pub fn calculate_ray(&self, u: f64, v: f64) -> Ray {
+    /// by offset vectors `u` and `v`. The direction is computed relative to the origin, so it does
+    /// not lose precision when the camera is far from the origin of the scene.
+    fn calculate_ray(&self, u: f64, v: f64) -> Ray {
         Ray::new(
             self.origin,
-            (self.lower_left_corner + (u as f64 * &self.horizontal)) + (v as f64 * &self.vertical)
-                - self.origin,
+            self.lower_left_direction + u * &self.horizontal + v * &self.vertical,
+        )
+    }
+
+    /// Ratio of the sensor width to its height.
+    fn aspect_ratio(&self) -> f64 {
+        self.horizontal.length() / self.vertical.length()
+    }
+}
+
+/// Camera with parallel rays (orthographic projection), sizes of objects do not depend on their
+/// distance from the camera.
+pub struct OrthographicCamera {
+    direction: Vec3,
+    horizontal: Vec3,
+    vertical: Vec3,
+    lower_left_corner: Point,
+}
+
+impl OrthographicCamera {
+    /// Creates a camera placed at `lookfrom` pointing towards `lookat`. `vup` determines the up
+    /// direction of the picture and `height` is the height of the viewed area in scene units.
+    pub fn look_at(
+        lookfrom: Point,
+        lookat: Point,
+        vup: Vec3,
+        height: f64,
+        aspect_ratio: f64,
+    ) -> OrthographicCamera {
+        let w = (lookfrom - lookat).unit();
+        let u = Vec3::cross(vup, w).unit();
+        let v = Vec3::cross(w, u);
+
+        let horizontal = (aspect_ratio * height) * &u;
+        let vertical = height * &v;
+
+        OrthographicCamera {
+            direction: -w,
+            horizontal,
+            vertical,
+            lower_left_corner: lookfrom - horizontal / 2.0 - vertical / 2.0,
+        }
+    }
+}
+
+impl Camera for OrthographicCamera {
+    /// All rays have the same direction, they start at the point of the viewport given by `u` and
+    /// `v`.
+    fn calculate_ray(&self, u: f64, v: f64) -> Ray {
+        Ray::new(
+            self.lower_left_corner + (u * &self.horizontal) + (v * &self.vertical),
+            self.direction,
         )
     }
+
+    fn aspect_ratio(&self) -> f64 {
+        self.horizontal.length() / self.vertical.length()
+    }
+}
+
+/// Camera capturing all directions around it (equirectangular projection, e.g. for environment
+/// maps). Horizontal axis of the image is the azimuth (360°), the vertical one is the elevation
+/// (180°), therefore the aspect ratio of the image is 2.
+pub struct PanoramicCamera {
+    origin: Point,
+    /// Direction in the center of the image
+    forward: Vec3,
+    right: Vec3,
+    up: Vec3,
+}
+
+impl PanoramicCamera {
+    /// Creates a camera placed at `lookfrom`, the center of the image shows `lookat`. `vup`
+    /// determines the up direction (the poles of the panorama).
+    pub fn look_at(lookfrom: Point, lookat: Point, vup: Vec3) -> PanoramicCamera {
+        let forward = (lookat - lookfrom).unit();
+        let right = Vec3::cross(forward, vup).unit();
+        let up = Vec3::cross(right, forward);
+
+        PanoramicCamera {
+            origin: lookfrom,
+            forward,
+            right,
+            up,
+        }
+    }
+}
+
+impl Camera for PanoramicCamera {
+    fn calculate_ray(&self, u: f64, v: f64) -> Ray {
+        let azimuth = (u - 0.5) * 2. * core::f64::consts::PI;
+        let elevation = (v - 0.5) * core::f64::consts::PI;
+        let (sin_azimuth, cos_azimuth) = math::sin_cos(azimuth);
+        let (sin_elevation, cos_elevation) = math::sin_cos(elevation);
+
+        let direction = (cos_elevation * cos_azimuth) * &self.forward
+            + (cos_elevation * sin_azimuth) * &self.right
+            + sin_elevation * &self.up;
+        Ray::new(self.origin, direction)
+    }
+
+    fn aspect_ratio(&self) -> f64 {
+        2.
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn centered_ray_points_at_lookat() {
+        let lookfrom = Point::new(3., 2., 5.);
+        let lookat = Point::new(-1., 0.5, -2.);
+        let camera = PerspectiveCamera::look_at(lookfrom, lookat, Vec3::new(0., 1., 0.), 40., 1.5);
+        let ray = camera.calculate_ray(0.5, 0.5);
+        assert!(ray.origin().approx_eq(&lookfrom, 1e-12));
+        let expected = (lookat - lookfrom).unit();
+        assert!(ray.direction().unit().approx_eq(&expected, 1e-12));
+    }
+
+    #[test]
+    fn corners_are_symmetric_around_the_view_axis() {
+        let camera = PerspectiveCamera::look_at(
+            Point::new(0., 0., 0.),
+            Point::new(0., 0., -1.),
+            Vec3::new(0., 1., 0.),
+            90.,
+            2.,
+        );
+        let lower_left = camera.calculate_ray(0., 0.).direction();
+        let upper_right = camera.calculate_ray(1., 1.).direction();
+        assert!(lower_left.approx_eq(&Vec3::new(-2., -1., -1.), 1e-12));
+        assert!(upper_right.approx_eq(&Vec3::new(2., 1., -1.), 1e-12));
+        assert!((camera.aspect_ratio() - 2.).abs() < 1e-12);
+    }
+
+    #[test]
+    fn long_focal_length_keeps_directions_near_unit_length() {
+        let camera = PerspectiveCamera::new(2., 1.5, 100.);
+        for &(u, v) in &[(0., 0.), (0.5, 0.5), (1., 1.), (0.25, 0.75)] {
+            let direction = camera.calculate_ray(u, v).direction();
+            assert!((direction.length() - 1.).abs() < 1e-3);
+        }
+        // The sensor spans 1/100 of the distance in every unit of its size
+        let lower_left = camera.calculate_ray(0., 0.).direction();
+        assert!(lower_left.approx_eq(&Vec3::new(-0.015, -0.01, -1.), 1e-12));
+    }
 }
@@ -0,0 +1,173 @@
+use image::{ImageBuffer, Pixel};
+use std::convert::TryInto;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Identifies the checkpoint file format (and its version).
+const MAGIC: &[u8; 4] = b"RTC2";
+const HEADER_LEN: usize = 21;
+
+/// Sidecar file with finished scanlines of a render, so that an interrupted render can be resumed.
+///
+/// The file consists of a header (magic, image width, height, number of channels and a key of the
+/// render given by the caller) followed by
+/// records, each of them holds the row index and raw pixel data of the whole row. Records are only
+/// appended, incomplete record at the end of the file (interrupted write) is discarded on load.
+pub(crate) struct Checkpoint {
+    path: PathBuf,
+    file: File,
+    row_len: usize,
+    /// Bitset of finished rows
+    done: Vec<u64>,
+}
+
+impl Checkpoint {
+    /// Opens (or creates) the checkpoint at `path`. Rows already stored in the file are copied into
+    /// `buffer`. If the file belongs to an image of other dimensions or its key differs from `key`
+    /// (e.g. a hash of the render settings), it is started over.
+    pub(crate) fn open<P>(
+        path: &Path,
+        buffer: &mut ImageBuffer<P, Vec<u8>>,
+        key: u64,
+    ) -> io::Result<Self>
+    where
+        P: Pixel<Subpixel = u8> + 'static,
+    {
+        let (width, height) = buffer.dimensions();
+        let channels = P::CHANNEL_COUNT;
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&width.to_le_bytes());
+        header.extend_from_slice(&height.to_le_bytes());
+        header.push(channels);
+        header.extend_from_slice(&key.to_le_bytes());
+
+        let mut checkpoint = Checkpoint {
+            path: path.to_path_buf(),
+            file: OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)?,
+            row_len: (width * channels as u32) as usize,
+            done: vec![0; (height as usize).div_ceil(64)],
+        };
+
+        let mut content = Vec::new();
+        checkpoint.file.read_to_end(&mut content)?;
+
+        let valid_len = if content.len() >= HEADER_LEN && content[..HEADER_LEN] == header[..] {
+            checkpoint.load_rows(&content, buffer)
+        } else {
+            if !content.is_empty() {
+                log::warn!(
+                    "Checkpoint {} does not match the image, ignoring it",
+                    path.display()
+                );
+            }
+            checkpoint.file.set_len(0)?;
+            checkpoint.file.seek(SeekFrom::Start(0))?;
+            checkpoint.file.write_all(&header)?;
+            HEADER_LEN
+        };
+        // Drops the incomplete record (if any), so that new records are appended right after the
+        // last valid one.
+        checkpoint.file.set_len(valid_len as u64)?;
+        checkpoint.file.seek(SeekFrom::Start(valid_len as u64))?;
+
+        Ok(checkpoint)
+    }
+
+    /// Copies rows stored in `content` into `buffer`, returns length of the valid part of the file.
+    fn load_rows<P>(&mut self, content: &[u8], buffer: &mut ImageBuffer<P, Vec<u8>>) -> usize
+    where
+        P: Pixel<Subpixel = u8> + 'static,
+    {
+        let height = buffer.height();
+        let raw: &mut [u8] = buffer;
+        let record_len = 4 + self.row_len;
+        let mut offset = HEADER_LEN;
+        while offset + record_len <= content.len() {
+            let row = u32::from_le_bytes(content[offset..offset + 4].try_into().unwrap());
+            if row >= height {
+                break;
+            }
+            let start = row as usize * self.row_len;
+            let data = &content[offset + 4..offset + record_len];
+            raw[start..start + self.row_len].copy_from_slice(data);
+            self.mark_done(row);
+            offset += record_len;
+        }
+        log::info!(
+            "Loaded {} finished lines from the checkpoint",
+            self.finished_rows()
+        );
+        offset
+    }
+
+    pub(crate) fn is_done(&self, row: u32) -> bool {
+        self.done[row as usize / 64] & (1 << (row % 64)) != 0
+    }
+
+    fn mark_done(&mut self, row: u32) {
+        self.done[row as usize / 64] |= 1 << (row % 64);
+    }
+
+    fn finished_rows(&self) -> u32 {
+        self.done.iter().map(|bits| bits.count_ones()).sum()
+    }
+
+    /// Appends finished `row` of the `buffer` to the checkpoint file.
+    pub(crate) fn save_row<P>(
+        &mut self,
+        row: u32,
+        buffer: &ImageBuffer<P, Vec<u8>>,
+    ) -> io::Result<()>
+    where
+        P: Pixel<Subpixel = u8> + 'static,
+    {
+        let start = row as usize * self.row_len;
+        let mut record = Vec::with_capacity(4 + self.row_len);
+        record.extend_from_slice(&row.to_le_bytes());
+        record.extend_from_slice(&buffer.as_raw()[start..start + self.row_len]);
+        self.file.write_all(&record)?;
+        self.mark_done(row);
+        Ok(())
+    }
+
+    /// The render is complete, the checkpoint is not needed anymore.
+    pub(crate) fn remove(self) -> io::Result<()> {
+        std::fs::remove_file(&self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn checkpoint_of_other_render_is_started_over() {
+        let path = std::env::temp_dir().join(format!("checkpoint-{}", std::process::id()));
+        let mut buffer: ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::new(4, 3);
+        buffer.put_pixel(2, 1, Rgba([1, 2, 3, 4]));
+        Checkpoint::open(&path, &mut buffer, 7)
+            .unwrap()
+            .save_row(1, &buffer)
+            .unwrap();
+
+        let mut resumed: ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::new(4, 3);
+        let checkpoint = Checkpoint::open(&path, &mut resumed, 7).unwrap();
+        assert!(checkpoint.is_done(1));
+        assert_eq!(resumed.get_pixel(2, 1), &Rgba([1, 2, 3, 4]));
+
+        let mut other: ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::new(4, 3);
+        let checkpoint = Checkpoint::open(&path, &mut other, 8).unwrap();
+        assert!(!checkpoint.is_done(1));
+        assert_eq!(other.get_pixel(2, 1), &Rgba([0, 0, 0, 0]));
+        checkpoint.remove().unwrap();
+    }
+}
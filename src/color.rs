@@ -1,3 +1,149 @@
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::math;
+use crate::texture::ColorSpace;
+
+/// How the averaged (linear) samples are encoded into the output image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    /// Gamma correction with gamma 2.0, suitable for viewing the image directly. It approximates
+    /// `Srgb`, shadows are slightly darker.
+    Gamma2,
+    /// The sRGB transfer function (linear segment near black, exponent 2.4 elsewhere), which is
+    /// what displays and image viewers expect.
+    Srgb,
+    /// Linear values are written as they are, e.g. for external tone mappers or data passes
+    /// (depth, normals) where gamma correction would distort the values.
+    Linear,
+}
+
+impl Encoding {
+    /// Encodes the linear component `x` by the transfer function, negative values (and NaN)
+    /// become 0.
+    pub fn encode(self, x: f64) -> f64 {
+        match self {
+            Encoding::Gamma2 => math::sqrt(non_negative(x)),
+            Encoding::Srgb => srgb_encode(non_negative(x)),
+            Encoding::Linear => non_negative(x),
+        }
+    }
+
+    /// Inverse of `encode`, converts the encoded component `x` (from 0 to 1) back to linear.
+    pub fn decode(self, x: f64) -> f64 {
+        match self {
+            Encoding::Gamma2 => x * x,
+            Encoding::Srgb => ColorSpace::Srgb.to_linear(x),
+            Encoding::Linear => x,
+        }
+    }
+}
+
+/// How components too bright for the image (larger than 1 after encoding) are handled.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Highlights {
+    /// Values are clipped to 1, bright areas lose all detail.
+    Clip,
+    /// Values above the threshold (from 0 to 1) are smoothly compressed into the remaining range
+    /// below 1, which looks less harsh on bright surfaces and specular highlights.
+    Knee(f64),
+}
+
+impl Highlights {
+    fn compress(self, x: f64) -> f64 {
+        match self {
+            Highlights::Clip => x,
+            Highlights::Knee(threshold) if x > threshold && threshold < 1. => {
+                // Exponential rolloff, it continues the identity with the same slope at the
+                // threshold and approaches 1 for large values.
+                let range = 1. - threshold;
+                threshold + range * (1. - math::exp(-(x - threshold) / range))
+            }
+            Highlights::Knee(_) => x,
+        }
+    }
+}
+
+/// One step of turning averaged linear colors into the values of the image (e.g. exposure, tone
+/// mapping or the transfer function), see `ColorPipeline`.
+pub trait ColorOperation: Send + Sync {
+    fn apply(&self, color: Color) -> Color;
+}
+
+/// Encodes the components by the transfer function, negative values (and NaN) become 0.
+impl ColorOperation for Encoding {
+    fn apply(&self, color: Color) -> Color {
+        Color::new(
+            self.encode(color.r),
+            self.encode(color.g),
+            self.encode(color.b),
+        )
+    }
+}
+
+/// Handles the components too bright for the image, it is meant to follow the transfer function.
+impl ColorOperation for Highlights {
+    fn apply(&self, color: Color) -> Color {
+        Color::new(
+            self.compress(color.r),
+            self.compress(color.g),
+            self.compress(color.b),
+        )
+    }
+}
+
+/// Scales the linear colors by `2^stops`, e.g. 1 makes the image twice as bright.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Exposure {
+    stops: f64,
+}
+
+impl Exposure {
+    pub fn new(stops: f64) -> Exposure {
+        Exposure { stops }
+    }
+}
+
+impl ColorOperation for Exposure {
+    fn apply(&self, color: Color) -> Color {
+        math::powf(2., self.stops) * color
+    }
+}
+
+/// Chain of operations applied (in the order they were added) to the average of the samples of
+/// every pixel, before it is quantized to 8 bits. Components outside of range from 0 to 1 are
+/// clipped by the quantization.
+#[derive(Default)]
+pub struct ColorPipeline {
+    operations: Vec<Box<dyn ColorOperation>>,
+}
+
+impl ColorPipeline {
+    /// Pipeline without any operation, linear colors are only clipped and quantized.
+    pub fn new() -> ColorPipeline {
+        ColorPipeline::default()
+    }
+
+    /// Pipeline of `Color::combine_samples`: `encoding`, then `highlights`.
+    pub fn standard(encoding: Encoding, highlights: Highlights) -> ColorPipeline {
+        ColorPipeline::new().then(encoding).then(highlights)
+    }
+
+    /// Appends the `operation` to the end of the chain.
+    pub fn then<T: ColorOperation + 'static>(mut self, operation: T) -> ColorPipeline {
+        self.operations.push(Box::new(operation));
+        self
+    }
+
+    pub fn apply(&self, color: Color) -> Color {
+        self.operations
+            .iter()
+            .fold(color, |color, operation| operation.apply(color))
+    }
+}
+
+#[derive(Debug)]
 pub struct Color {
     r: f64,
     g: f64,
@@ -13,9 +159,26 @@ impl Color {
         }
     }
 
+    /// Components are not limited to range from 0 to 1 (e.g. colors of lights or HDR images).
+    pub fn new(r: f64, g: f64, b: f64) -> Color {
+        Color { r, g, b }
+    }
+
+    pub fn r(&self) -> f64 {
+        self.r
+    }
+
+    pub fn g(&self) -> f64 {
+        self.g
+    }
+
+    pub fn b(&self) -> f64 {
+        self.b
+    }
+
     /// Returns `None` if any of the arguments is larger than 1
     pub fn from_frac(r: f64, g: f64, b: f64) -> Option<Color> {
-        if r > 1. || r < 0. || g > 1. || g < 0. || b > 1. || b < 0. {
+        if !(0. ..=1.).contains(&r) || !(0. ..=1.).contains(&g) || !(0. ..=1.).contains(&b) {
             None
         } else {
             Some(Color { r, g, b })
@@ -42,24 +205,38 @@ impl Color {
     /// Combines samples to get final color of the pixel using "white noise" method.
     ///
     /// `Color` on which the method is called, is expected to be sum of samples (how many of them is
-    /// given by parameter `samples`).
-    pub fn combine_samples(&mut self, samples: u16) {
-        // Scale and gamma-correct for gamma=2.0 (sqrt).
-        let scale = 1.0 / samples as f64;
-        self.r = (self.r * scale).sqrt();
-        self.g = (self.g * scale).sqrt();
-        self.b = (self.b * scale).sqrt();
+    /// given by parameter `samples`). The average is then encoded according to `encoding` and too
+    /// bright values are handled according to `highlights` (see `ColorPipeline::standard`).
+    pub fn combine_samples(&mut self, samples: u16, encoding: Encoding, highlights: Highlights) {
+        self.combine_weighted(samples as f64, encoding, highlights);
+    }
+
+    /// Same as `combine_samples` for samples that were not summed up with equal weights (e.g. by
+    /// a reconstruction filter), `weight` is the sum of their weights.
+    pub fn combine_weighted(&mut self, weight: f64, encoding: Encoding, highlights: Highlights) {
+        self.combine_with(weight, &ColorPipeline::standard(encoding, highlights));
+    }
+
+    /// Same as `combine_weighted`, the average is processed by the operations of the `pipeline`.
+    pub fn combine_with(&mut self, weight: f64, pipeline: &ColorPipeline) {
+        let average = (1.0 / weight) * self.copy();
+        self.quantize(pipeline.apply(average));
+    }
 
-        // Transform each component to [0,255] range
-        self.r = 256.0 * clamp(self.r, 0.0, 0.999);
-        self.g = 256.0 * clamp(self.g, 0.0, 0.999);
-        self.b = 256.0 * clamp(self.b, 0.0, 0.999);
+    /// Sets the components to `color` transformed from range from 0 to 1 to range from 0 to 255.
+    /// Negative values (and NaN) were replaced by 0 by the encoding, so that one broken sample does
+    /// not spoil the pixel.
+    fn quantize(&mut self, color: Color) {
+        self.r = 256.0 * clamp(color.r, 0.0, 0.999);
+        self.g = 256.0 * clamp(color.g, 0.0, 0.999);
+        self.b = 256.0 * clamp(color.b, 0.0, 0.999);
     }
 
     pub fn get_u8(self) -> [u8; 3] {
         [self.r as u8, self.g as u8, self.b as u8]
     }
 
+    /// Light blue of the default sky
     pub fn blue() -> Color {
         Color::from_frac(0.5, 0.7, 1.0).unwrap()
     }
@@ -68,6 +245,20 @@ impl Color {
         Color::from_frac(1.0, 1.0, 1.0).unwrap()
     }
 
+    pub fn red() -> Color {
+        Color::from_frac(1.0, 0.0, 0.0).unwrap()
+    }
+
+    pub fn green() -> Color {
+        Color::from_frac(0.0, 1.0, 0.0).unwrap()
+    }
+
+    /// Linear interpolation, returns `a` for `t` = 0 and `b` for `t` = 1. `t` outside of this range
+    /// extrapolates.
+    pub fn lerp(a: &Color, b: &Color, t: f64) -> Color {
+        (1. - t) * a.copy() + t * b.copy()
+    }
+
     pub fn copy(&self) -> Color {
         Color {
             r: self.r,
@@ -75,6 +266,161 @@ impl Color {
             b: self.b,
         }
     }
+
+    /// Returns the color scaled down so that none of its components is larger than `max`, the
+    /// ratios of the components (hue) are kept. Colors within the limit are returned unchanged.
+    pub fn limited(self, max: f64) -> Color {
+        let largest = self.r.max(self.g).max(self.b);
+        if largest > max {
+            (max / largest) * self
+        } else {
+            self
+        }
+    }
+
+    /// Perceived brightness of the (linear) color, weighted by the Rec. 709 coefficients.
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
+    /// Returns `true` if all components differ by at most `eps`.
+    pub fn approx_eq(&self, other: &Color, eps: f64) -> bool {
+        (self.r - other.r).abs() <= eps
+            && (self.g - other.g).abs() <= eps
+            && (self.b - other.b).abs() <= eps
+    }
+}
+
+impl core::fmt::Display for Color {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "rgb({}, {}, {})", self.r, self.g, self.b)
+    }
+}
+
+/// Linear colors of a rendered image (averages of the samples, before gamma correction and
+/// conversion to 8 bits), row by row from the top. Values are not limited to range from 0 to 1, so
+/// that the image can be post-processed (e.g. histograms or custom tone mapping).
+///
+/// The number of samples every pixel is averaged from is kept as well, it differs between pixels
+/// of time-limited renders.
+pub struct ColorBuffer {
+    width: u32,
+    height: u32,
+    pixels: Vec<Color>,
+    samples: Vec<u32>,
+}
+
+impl ColorBuffer {
+    /// All pixels are black, with no samples.
+    pub fn new(width: u32, height: u32) -> ColorBuffer {
+        let size = width as usize * height as usize;
+        ColorBuffer {
+            width,
+            height,
+            pixels: (0..size).map(|_| Color::black()).collect(),
+            samples: vec![0; size],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Panics if the coordinates are out of the image.
+    pub fn get(&self, x: u32, y: u32) -> &Color {
+        &self.pixels[self.index(x, y)]
+    }
+
+    /// Panics if the coordinates are out of the image.
+    pub fn put(&mut self, x: u32, y: u32, color: Color) {
+        let index = self.index(x, y);
+        self.pixels[index] = color;
+    }
+
+    /// Number of samples the pixel is averaged from. Panics if the coordinates are out of the image.
+    pub fn samples(&self, x: u32, y: u32) -> u32 {
+        self.samples[self.index(x, y)]
+    }
+
+    /// Panics if the coordinates are out of the image.
+    pub fn set_samples(&mut self, x: u32, y: u32, samples: u32) {
+        let index = self.index(x, y);
+        self.samples[index] = samples;
+    }
+
+    /// Iterates over the pixels row by row.
+    pub fn iter(&self) -> core::slice::Iter<'_, Color> {
+        self.pixels.iter()
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        assert!(
+            x < self.width && y < self.height,
+            "Pixel ({}, {}) is out of the {}x{} image.",
+            x,
+            y,
+            self.width,
+            self.height
+        );
+        y as usize * self.width as usize + x as usize
+    }
+}
+
+/// Pixel at coordinates `(x, y)`
+impl core::ops::Index<(u32, u32)> for ColorBuffer {
+    type Output = Color;
+
+    fn index(&self, (x, y): (u32, u32)) -> &Color {
+        self.get(x, y)
+    }
+}
+
+impl core::ops::IndexMut<(u32, u32)> for ColorBuffer {
+    fn index_mut(&mut self, (x, y): (u32, u32)) -> &mut Color {
+        let index = ColorBuffer::index(self, x, y);
+        &mut self.pixels[index]
+    }
+}
+
+impl IntoIterator for ColorBuffer {
+    type Item = Color;
+    type IntoIter = alloc::vec::IntoIter<Color>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.pixels.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a ColorBuffer {
+    type Item = &'a Color;
+    type IntoIter = core::slice::Iter<'a, Color>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Returns 0 for negative values and NaN, otherwise `x` is returned unchanged.
+fn non_negative(x: f64) -> f64 {
+    if x > 0. {
+        x
+    } else {
+        0.
+    }
+}
+
+/// Encodes non-negative linear value `x` by the sRGB transfer function (inverse of
+/// `ColorSpace::Srgb` of textures).
+fn srgb_encode(x: f64) -> f64 {
+    if x <= 0.0031308 {
+        12.92 * x
+    } else {
+        1.055 * math::powf(x, 1. / 2.4) - 0.055
+    }
 }
 
 /// Clamp value x to the range [min, max]
@@ -85,10 +431,10 @@ fn clamp(x: f64, min: f64, max: f64) -> f64 {
     if x > max {
         return max;
     }
-    return x;
+    x
 }
 
-impl std::ops::Mul<Color> for f64 {
+impl core::ops::Mul<Color> for f64 {
     type Output = Color;
 
     fn mul(self, rhs: Color) -> Color {
@@ -100,7 +446,7 @@ impl std::ops::Mul<Color> for f64 {
     }
 }
 
-impl std::ops::Mul<Color> for Color {
+impl core::ops::Mul<Color> for Color {
     type Output = Color;
     fn mul(self, rhs: Color) -> Color {
         Color {
@@ -111,7 +457,7 @@ impl std::ops::Mul<Color> for Color {
     }
 }
 
-impl std::ops::Add for Color {
+impl core::ops::Add for Color {
     type Output = Color;
 
     fn add(self, rhs: Self) -> Color {
@@ -122,3 +468,40 @@ impl std::ops::Add for Color {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lerp_returns_endpoints_and_midpoint() {
+        let a = Color::new(0., 0.5, 1.);
+        let b = Color::new(1., 0.5, 3.);
+        assert!(Color::lerp(&a, &b, 0.).approx_eq(&a, 1e-12));
+        assert!(Color::lerp(&a, &b, 1.).approx_eq(&b, 1e-12));
+        assert!(Color::lerp(&a, &b, 0.5).approx_eq(&Color::new(0.5, 0.5, 2.), 1e-12));
+    }
+
+    #[test]
+    fn named_colors_have_expected_components() {
+        assert!(Color::black().approx_eq(&Color::new(0., 0., 0.), 0.));
+        assert!(Color::white().approx_eq(&Color::new(1., 1., 1.), 0.));
+        assert!(Color::red().approx_eq(&Color::new(1., 0., 0.), 0.));
+        assert!(Color::green().approx_eq(&Color::new(0., 1., 0.), 0.));
+        assert!(Color::blue().approx_eq(&Color::new(0.5, 0.7, 1.), 0.));
+    }
+
+    #[test]
+    fn combine_samples_turns_negative_components_into_black() {
+        let mut color = Color::new(-2., 1., -0.5);
+        color.combine_samples(4, Encoding::Gamma2, Highlights::Clip);
+        assert_eq!(color.get_u8(), [0, 128, 0]);
+    }
+
+    #[test]
+    fn combine_samples_turns_nan_into_black() {
+        let mut color = Color::new(f64::NAN, 4., 0.);
+        color.combine_samples(4, Encoding::Srgb, Highlights::Clip);
+        assert_eq!(color.get_u8(), [0, 255, 0]);
+    }
+}
@@ -0,0 +1,98 @@
+use image::{ImageBuffer, Rgba};
+
+use crate::color::Encoding;
+
+/// Rendered image prepared for compositing with other images (e.g. an object rendered with
+/// transparent background placed over a separately rendered environment). Color components are
+/// linear (not gamma-corrected), alpha is straight (not premultiplied), all values are in range
+/// from 0 to 1.
+pub type Layer = ImageBuffer<Rgba<f32>, Vec<f32>>;
+
+/// Converts the rendered image to a layer. The color components are encoded by `encoding` (the
+/// `color_encoding` of the render), the encoding is reverted, so that colors can be mixed
+/// linearly.
+pub fn from_image(image: &ImageBuffer<Rgba<u8>, Vec<u8>>, encoding: Encoding) -> Layer {
+    ImageBuffer::from_fn(image.width(), image.height(), |x, y| {
+        let p = image.get_pixel(x, y).0;
+        let linear = |c: u8| encoding.decode(c as f64 / 255.) as f32;
+        Rgba([linear(p[0]), linear(p[1]), linear(p[2]), p[3] as f32 / 255.])
+    })
+}
+
+/// Converts the layer back to an image that can be saved, the colors are encoded by `encoding`.
+pub fn to_image(layer: &Layer, encoding: Encoding) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    ImageBuffer::from_fn(layer.width(), layer.height(), |x, y| {
+        let p = layer.get_pixel(x, y).0;
+        let encode = |c: f32| (255. * encoding.encode(c.clamp(0., 1.) as f64)).round() as u8;
+        Rgba([
+            encode(p[0]),
+            encode(p[1]),
+            encode(p[2]),
+            (255. * p[3].clamp(0., 1.)).round() as u8,
+        ])
+    })
+}
+
+/// Places `fg` over `bg` (Porter-Duff "over" operator), `bg` is visible through transparent parts
+/// of `fg`. Panics if the layers have different dimensions.
+pub fn over(fg: &Layer, bg: &Layer) -> Layer {
+    combine(fg, bg, |f, b| {
+        let alpha = f[3] + b[3] * (1. - f[3]);
+        if alpha == 0. {
+            return [0.; 4];
+        }
+        let mix = |i: usize| (f[i] * f[3] + b[i] * b[3] * (1. - f[3])) / alpha;
+        [mix(0), mix(1), mix(2), alpha]
+    })
+}
+
+/// Adds light of both layers together (e.g. separately rendered light sources). Alpha is the
+/// alpha of the more opaque layer. Panics if the layers have different dimensions.
+pub fn add(a: &Layer, b: &Layer) -> Layer {
+    combine(a, b, |a, b| {
+        let alpha = a[3].max(b[3]);
+        if alpha == 0. {
+            return [0.; 4];
+        }
+        let sum = |i: usize| (a[i] * a[3] + b[i] * b[3]) / alpha;
+        [sum(0), sum(1), sum(2), alpha]
+    })
+}
+
+fn combine<F>(a: &Layer, b: &Layer, f: F) -> Layer
+where
+    F: Fn([f32; 4], [f32; 4]) -> [f32; 4],
+{
+    assert_eq!(
+        a.dimensions(),
+        b.dimensions(),
+        "Only layers of the same dimensions can be combined."
+    );
+    ImageBuffer::from_fn(a.width(), a.height(), |x, y| {
+        Rgba(f(a.get_pixel(x, y).0, b.get_pixel(x, y).0))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layer_round_trips_every_encoding() {
+        let image = ImageBuffer::from_fn(256, 1, |x, _| {
+            let v = x as u8;
+            Rgba([v, 255 - v, v / 2, 255 - v / 3])
+        });
+        for encoding in [Encoding::Gamma2, Encoding::Srgb, Encoding::Linear] {
+            assert_eq!(to_image(&from_image(&image, encoding), encoding), image);
+        }
+    }
+
+    #[test]
+    fn srgb_layer_is_linear() {
+        let image = ImageBuffer::from_pixel(1, 1, Rgba([188, 188, 188, 255]));
+        let layer = from_image(&image, Encoding::Srgb);
+        // sRGB 188 is about half of the light of white
+        assert!((layer.get_pixel(0, 0)[0] - 0.5).abs() < 0.01);
+    }
+}
@@ -0,0 +1,132 @@
+use core::f64::consts::PI;
+
+use crate::color::Color;
+use crate::math;
+use crate::random;
+use crate::texture::{ImageTexture, Texture};
+use crate::vec3::Vec3;
+
+/// Image surrounding the whole scene (e.g. an HDR photo of the sky), which lights the scene from
+/// all directions. The image is mapped onto an infinitely large sphere using the equirectangular
+/// projection, its top row is straight up.
+///
+/// Directions towards bright parts of the image (e.g. the sun) can be sampled by shadow rays, which
+/// is much less noisy than waiting for randomly scattered rays to find them.
+pub struct EnvironmentMap {
+    texture: ImageTexture,
+    /// Cumulative distribution of choosing a row (from the top of the image), normalized to 1.
+    row_cdf: Vec<f64>,
+    /// Cumulative distributions of choosing a pixel within every row, normalized to 1.
+    column_cdfs: Vec<Vec<f64>>,
+    /// Probability of choosing every pixel (row by row), normalized to 1.
+    pixel_probabilities: Vec<f64>,
+}
+
+impl EnvironmentMap {
+    /// Pixels are sampled proportionally to their luminance and the solid angle they cover (pixels
+    /// near the poles are smaller). Returns `None` if the image does not emit any light.
+    pub fn new(texture: ImageTexture) -> Option<EnvironmentMap> {
+        let (width, height) = (texture.width(), texture.height());
+        let mut weights = Vec::with_capacity((width * height) as usize);
+        for j in 0..height {
+            let v = 1. - (j as f64 + 0.5) / height as f64;
+            let sin_theta = math::sin(v * PI);
+            for i in 0..width {
+                let u = (i as f64 + 0.5) / width as f64;
+                let luminance = texture.value(u, v, Vec3::zero()).luminance();
+                weights.push(luminance.max(0.) * sin_theta);
+            }
+        }
+
+        let total: f64 = weights.iter().sum();
+        if !(total > 0. && total.is_finite()) {
+            return None;
+        }
+        let pixel_probabilities: Vec<f64> = weights.iter().map(|w| w / total).collect();
+
+        let mut row_cdf = Vec::with_capacity(height as usize);
+        let mut column_cdfs = Vec::with_capacity(height as usize);
+        let mut row_sum = 0.;
+        for row in pixel_probabilities.chunks(width as usize) {
+            let row_probability: f64 = row.iter().sum();
+            row_sum += row_probability;
+            row_cdf.push(row_sum);
+            column_cdfs.push(cumulative(row));
+        }
+
+        Some(EnvironmentMap {
+            texture,
+            row_cdf,
+            column_cdfs,
+            pixel_probabilities,
+        })
+    }
+
+    /// Returns the color of the environment seen in the `direction`.
+    pub fn value(&self, direction: Vec3) -> Color {
+        let (u, v) = direction_uv(direction.unit());
+        self.texture.value(u, v, Vec3::zero())
+    }
+
+    /// Returns a random unit direction, bright parts of the environment are chosen more often.
+    pub fn sample(&self) -> Vec3 {
+        let j = search(&self.row_cdf, random::random_range(0., 1.));
+        let i = search(&self.column_cdfs[j], random::random_range(0., 1.));
+        let (width, height) = (self.texture.width() as f64, self.texture.height() as f64);
+        let u = (i as f64 + random::random_range(0., 1.)) / width;
+        let v = 1. - (j as f64 + random::random_range(0., 1.)) / height;
+        uv_direction(u, v)
+    }
+
+    /// Returns the probability density (with respect to solid angle) of generating the `direction`
+    /// by `sample`.
+    pub fn pdf_value(&self, direction: Vec3) -> f64 {
+        let (u, v) = direction_uv(direction.unit());
+        let sin_theta = math::sin(v * PI);
+        if sin_theta <= 0. {
+            return 0.;
+        }
+        let (width, height) = (self.texture.width(), self.texture.height());
+        let i = ((u * width as f64) as u32).min(width - 1);
+        let j = (((1. - v) * height as f64) as u32).min(height - 1);
+        // Density over the image (area 1) is converted to the sphere, where the row at angle theta
+        // from the bottom is stretched by 2 pi sin(theta) horizontally and by pi vertically.
+        let density = self.pixel_probabilities[(j * width + i) as usize] * (width * height) as f64;
+        density / (2. * PI * PI * sin_theta)
+    }
+}
+
+/// Returns cumulative sums of `probabilities` scaled so that the last one is 1 (empty rows have
+/// a uniform distribution, they are never chosen anyway).
+fn cumulative(probabilities: &[f64]) -> Vec<f64> {
+    let total: f64 = probabilities.iter().sum();
+    let count = probabilities.len() as f64;
+    let mut sum = 0.;
+    probabilities
+        .iter()
+        .map(|p| {
+            sum += if total > 0. { p / total } else { 1. / count };
+            sum
+        })
+        .collect()
+}
+
+/// Returns index of the first value of the cumulative distribution `cdf` larger than `x`.
+fn search(cdf: &[f64], x: f64) -> usize {
+    cdf.partition_point(|&c| c <= x).min(cdf.len() - 1)
+}
+
+/// Texture coordinates of the unit `direction`, the same as on a sphere (see `Sphere::hit`): `u`
+/// goes around the Y axis starting at X=-1, `v` from Y=-1 to Y=+1.
+fn direction_uv(direction: Vec3) -> (f64, f64) {
+    let theta = math::acos((-direction.y()).clamp(-1., 1.));
+    let phi = math::atan2(-direction.z(), direction.x()) + PI;
+    (phi / (2. * PI), theta / PI)
+}
+
+/// Inverse of `direction_uv`.
+fn uv_direction(u: f64, v: f64) -> Vec3 {
+    let (sin_theta, cos_theta) = math::sin_cos(v * PI);
+    let (sin_phi, cos_phi) = math::sin_cos(u * 2. * PI);
+    Vec3::new(-cos_phi * sin_theta, -cos_theta, sin_phi * sin_theta)
+}
@@ -9,6 +9,14 @@ pub struct HitRecord {
     pub(crate) normal: Vec3,
     // Parameter that says where on the ray the intersection happened.
     pub(crate) t: f64,
+    // Texture coordinates of the point of intersection (both from 0 to 1).
+    pub(crate) u: f64,
+    pub(crate) v: f64,
+    // Unit vectors perpendicular to the normal pointing in the directions of increasing `u` and `v`
+    // (tangent space for normal mapping or anisotropic materials). `None` if the object does not
+    // provide them.
+    pub(crate) tangent: Option<Vec3>,
+    pub(crate) bitangent: Option<Vec3>,
 }
 
 impl HitRecord {
@@ -19,6 +27,28 @@ impl HitRecord {
             point: Point::zero(),
             normal: Vec3::zero(),
             t: 0.0,
+            u: 0.0,
+            v: 0.0,
+            tangent: None,
+            bitangent: None,
         }
     }
+
+    // Restores the default values, so that the record can be reused for another intersection test
+    // (objects set only the data they have, e.g. tangents are left as they are).
+    pub fn reset(&mut self) {
+        *self = HitRecord::new();
+    }
+
+    // Parameter of the ray at the point of intersection, e.g. for narrowing the interval of the
+    // next intersection tests.
+    pub fn t(&self) -> f64 {
+        self.t
+    }
+}
+
+impl Default for HitRecord {
+    fn default() -> Self {
+        Self::new()
+    }
 }
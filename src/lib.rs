@@ -1,205 +1,114 @@
-use image::{ImageBuffer, Rgb};
-use rand::{thread_rng, Rng};
-use std::sync::{mpsc, Arc};
-
-use camera::Sensor;
-use color::Color;
+//! Ray tracer following the "Ray Tracing in One Weekend" series.
+//!
+//! The `std` feature (enabled by default) provides the renderer itself (threads, images and
+//! files). Without it, the crate is `no_std` (it needs `alloc`) and provides the scene description
+//! and the math of the rays: vectors, colors, cameras, objects, materials and textures given by
+//! functions. Floating point functions are then computed by `libm` and random numbers come from
+//! the generator given to `set_random_source`.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use aabb::Aabb;
 use hit_record::HitRecord;
-use material::{Lambertian, Material, Metal};
-use objects::Sphere;
+use material::Material;
 use ray::Ray;
-use std::ops::Deref;
-use thread_pool::ThreadPool;
 use vec3::Vec3;
 use vec3::Vec3 as Point; // For better understanding of the code
 
-mod camera;
-mod color;
-mod hit_record;
-mod material;
-mod objects;
-mod ray;
-mod thread_pool;
-mod vec3;
-
-const INFINITY: f64 = f64::MAX;
-
-/// Supersampling anti-aliasing parameter
-const SAMPLES_PER_PIXEL: u16 = 16;
-/// Upper limit for ray reflections
-const MAX_DEPTH: u16 = 10;
-const THREAD_COUNT: u8 = 8;
-const OUTPUT_FILE_NAME: &str = "image.png";
-
-const IMAGE_WIDTH: u32 = 1920;
-const IMAGE_ASPECT_RATIO: f64 = 16.0 / 9.0;
-const CAM_FOCAL_LENGTH: f64 = 1.0;
-const CAM_HEIGHT: f64 = 2.0;
-
-/// Holds information about dimensions of the resulting image.
-struct Image {
-    width: u32,
-    height: u32,
-}
-
-impl Image {
-    fn new(width: u32, aspect_ratio: f64) -> Image {
-        Image {
-            width,
-            height: (width as f64 / aspect_ratio) as u32,
-        }
-    }
-}
+pub mod aabb;
+pub mod camera;
+#[cfg(feature = "std")]
+mod checkpoint;
+pub mod color;
+#[cfg(feature = "std")]
+pub mod compositing;
+#[cfg(feature = "std")]
+pub mod environment;
+pub mod hit_record;
+pub mod mat4;
+pub mod material;
+pub mod math;
+pub mod objects;
+#[cfg(feature = "std")]
+pub mod overlay;
+pub mod post_processing;
+mod random;
+pub mod ray;
+#[cfg(feature = "std")]
+mod render;
+pub mod scene;
+#[cfg(feature = "scene-macro")]
+mod scene_macro;
+#[cfg(feature = "batch-hit")]
+pub mod sphere_batch;
+#[cfg(feature = "std")]
+mod stats;
+pub mod texture;
+#[cfg(feature = "std")]
+pub mod thread_pool;
+pub mod vec3;
+
+/// Boxes the materials of `scene!`, the crate using the macro does not need `std`.
+#[cfg(feature = "scene-macro")]
+#[doc(hidden)]
+pub use alloc::boxed::Box as __SceneBox;
+#[cfg(not(feature = "std"))]
+pub use random::{set_random_source, RandomSource};
+#[cfg(feature = "std")]
+pub use render::{
+    calculate_image, calculate_image_into, debug_pixel, render, run, Background, Image, Integrator,
+    Options, PixelFilter, Progress, ProgressCallback, RenderConfig, RenderError, SceneChoice, Tile,
+    TileCallback,
+};
+#[cfg(feature = "scene-macro")]
+#[doc(hidden)]
+pub use scene_macro::SceneOutput as __SceneOutput;
+#[cfg(feature = "std")]
+pub use thread_pool::{PoolCreationError, ThreadPool};
 
 /// Trait implemented by every ray traced object
-trait Hittable {
+pub trait Hittable: Send + Sync {
     /// Returns `true` if the object and ray intersect. Data about intersection point closer to the
     /// camera are saved into `HitRecord` struct. Intersection point is calculated only on interval
     /// (t_min, t_man).
     fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool;
-}
-
-trait TraceableObjects: Hittable + Material {}
-
-fn set_scene_objects(objects: &mut Vec<Box<dyn TraceableObjects>>) {
-    let diffused = Box::new(Lambertian::new(Color::from_frac(0.8, 0.2, 0.2).unwrap()));
-    let sphere = Sphere::new(Point::new(0., 0., -1.), 0.5, diffused);
-    objects.push(Box::new(sphere));
-    let metal = Box::new(Metal::fuzzy(Color::from_frac(0.8, 0.8, 0.8).unwrap(), 0.3));
-    let sphere = Sphere::new(Point::new(-1., 0., -1.0), 0.5, metal);
-    objects.push(Box::new(sphere));
-    let metal = Box::new(Metal::shiny(Color::from_frac(0.5, 0.6, 0.6).unwrap()));
-    let sphere = Sphere::new(Point::new(1., 0., -1.0), 0.5, metal);
-    objects.push(Box::new(sphere));
-    let diffused = Box::new(Lambertian::new(Color::from_frac(0.05, 0.5, 0.05).unwrap()));
-    let sphere = Sphere::new(Point::new(0., -100.5, -1.), 100., diffused);
-    objects.push(Box::new(sphere));
-}
-
-pub fn run() {
-    let image = Image::new(IMAGE_WIDTH, IMAGE_ASPECT_RATIO);
-    let camera_viewport = Sensor::new(CAM_HEIGHT, IMAGE_ASPECT_RATIO, CAM_FOCAL_LENGTH);
-
-    let mut scene_objects: Vec<Box<dyn TraceableObjects>> = Vec::new();
-    set_scene_objects(&mut scene_objects);
-
-    let image_buffer = calculate_image(camera_viewport, image, scene_objects);
-    save_image(&image_buffer, OUTPUT_FILE_NAME);
-}
-
-/// Iterates over every pixel in the image, calculates its color and returns the resulting image.
-/// The whole computation is done in parallel (`THREAD_COUNT` constant).
-fn calculate_image(
-    cam: Sensor,
-    image: Image,
-    scene_objects: Vec<Box<dyn TraceableObjects + 'static>>,
-) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
-    let mut image_buffer = image::ImageBuffer::new(image.width, image.height);
-    let pool = ThreadPool::new(THREAD_COUNT).unwrap();
-    // Channel for transmitting results back to the main thread
-    let (sender, receiver) = mpsc::channel();
-
-    // Every thread needs to own this data
-    let cam = Arc::new(cam);
-    let image = Arc::new(image);
-    let scene_objects = Arc::new(scene_objects);
-
-    // `h` and `w` give us location of the pixel in the image
-    for h in 0..image.height {
-        let cam_clone = cam.clone();
-        let image_clone = image.clone();
-        let scene_objects_clone = scene_objects.clone();
-        let sender_clone = sender.clone();
 
-        pool.execute(move || {
-            for w in 0..image_clone.width {
-                let color = get_pixel_color(&cam_clone, &image_clone, &scene_objects_clone, h, w);
-                let image_color = image::Rgb(color.get_u8());
-
-                let tuple = (w, h, image_color);
-                sender_clone.send(tuple).unwrap();
-            }
-            log::info!("Finished rendering of line {}", h);
-        });
+    /// Returns `true` if the object implements `random_point` and `pdf_value`, so that emissive
+    /// objects can be sampled by shadow rays. Light of the others is found only by scattered rays.
+    fn can_be_sampled(&self) -> bool {
+        false
     }
-    // The original value has to be dropped, so that the receiving for loop below ends after all
-    // threads finish their work.
-    std::mem::drop(sender);
 
-    for incoming in receiver {
-        let (w, h, image_color) = incoming;
-        image_buffer.put_pixel(w, h, image_color);
+    /// Returns a random point on the surface of the object, used for aiming shadow rays from
+    /// `origin` at lights.
+    fn random_point(&self, origin: Point) -> Point {
+        origin
     }
 
-    image_buffer
-}
-
-/// Computes color of the pixel at coordinates `w` and `h`. Uses two offset vectors `u` and `v` to convert
-/// the image pixel location to a fraction from 0 to 1 (used with virtual viewport for ray calculation).
-///
-/// Uses Supersampling anti-aliasing with random algorithm (stochastic sampling).
-fn get_pixel_color(
-    cam_clone: &Arc<Sensor>,
-    image_clone: &Arc<Image>,
-    scene_objects_clone: &Arc<Vec<Box<dyn TraceableObjects>>>,
-    h: u32,
-    w: u32,
-) -> Color {
-    let mut color = Color::black();
-    for _ in 0..SAMPLES_PER_PIXEL {
-        let u: f64 = (w as f64 + random_double()) / (image_clone.width as f64 - 1.0);
-        let v: f64 = (image_clone.height as f64 - 1. - h as f64 + random_double())
-            / (image_clone.height as f64 - 1.0);
-
-        let ray = cam_clone.calculate_ray(u, v);
-        let sample_color = calculate_color(ray, scene_objects_clone, MAX_DEPTH);
-        color.add_sample(sample_color);
+    /// Returns probability density of generating the `direction` from `origin` by `random_point`.
+    /// Objects that cannot be sampled return 0.
+    fn pdf_value(&self, _origin: Point, _direction: Vec3) -> f64 {
+        0.0
     }
-    color.combine_samples(SAMPLES_PER_PIXEL);
-    color
-}
 
-/// This returns color based on the surface normal vector at the collision point with an object (or
-/// multiple collisions) or background color.
-fn calculate_color(ray: Ray, shapes: &Arc<Vec<Box<dyn TraceableObjects>>>, depth: u16) -> Color {
-    if depth == 0 {
-        return Color::black();
+    /// Returns `true` if the object has no surface that could be hit (e.g. sphere with zero
+    /// radius), such objects only slow down the rendering or produce invalid normals.
+    fn is_degenerate(&self) -> bool {
+        false
     }
 
-    let mut rec: HitRecord = HitRecord::new();
-    for s in shapes.deref() {
-        // https://raytracing.github.io/books/RayTracingInOneWeekend.html#diffusematerials/
-        if s.hit(&ray, 0.001, INFINITY, &mut rec) {
-            let new_ray = s.scatter(&rec, &ray);
-            return if new_ray.is_some() {
-                s.attenuation() * calculate_color(new_ray.unwrap(), shapes, depth - 1)
-            } else {
-                Color::black()
-            };
-        }
+    /// Returns the box containing the whole object, `None` if the object is not bounded.
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
     }
-    linearly_blend_colors(ray, Color::white(), Color::blue())
-}
-
-/// Returns linearly blended color depending on the ray coordinates.
-fn linearly_blend_colors(r: Ray, start_value: Color, end_value: Color) -> Color {
-    // Normalizing the vector => as value of y changes, the value of x has to change too =>
-    // resulting color is dependent on both coordinates
-    let unit_direction: Vec3 = r.unit_vector();
-
-    // Transform from ( -1.0 ... 1.0) to ( 0.0 ... 1.0)
-    let t = 0.5 * (unit_direction.y() + 1.0);
 
-    // blended_value = ( 1.0 - t ) start_value + t * end_value
-    (1.0 - t) * start_value + t * end_value
-}
-
-/// Returns random number in range from 0.0 (included) to 1.0 (excluded)
-fn random_double() -> f64 {
-    let mut rng = thread_rng();
-    rng.gen_range(0.0..1.0)
+    /// Returns `true` if the `point` is inside of the object (points on the surface included), e.g.
+    /// to find out whether a ray starts inside of a volume. Objects without an inside (or which do
+    /// not know it) return `false`.
+    fn contains(&self, _point: Point) -> bool {
+        false
+    }
 }
 
-fn save_image(image_buffer: &ImageBuffer<Rgb<u8>, Vec<u8>>, filename: &str) {
-    image_buffer.save(filename).unwrap();
-}
+pub trait TraceableObjects: Hittable + Material {}
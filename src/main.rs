@@ -1,7 +1,17 @@
 use env_logger::Env;
-use ray_tracing::run;
+use ray_tracing::{run, Options};
+
+const USAGE: &str = "Usage: ray-tracing [--scene default|random] [--count N] [--seed S] \
+     [--time-limit T] [--debug-pixel X,Y] [--checkpoint FILE]";
 
 fn main() {
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
-    run();
+    let options = match Options::from_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+    run(options);
 }
@@ -0,0 +1,154 @@
+use crate::math;
+use crate::vec3::Vec3;
+use crate::vec3::Vec3 as Point;
+
+/// 4×4 matrix used for affine transformations of points and vectors (row-major order). Points
+/// are treated as homogeneous coordinates `(x, y, z, 1)` and vectors as `(x, y, z, 0)`.
+#[derive(Clone, Copy, Debug)]
+pub struct Mat4 {
+    m: [[f64; 4]; 4],
+}
+
+impl Mat4 {
+    pub fn new(m: [[f64; 4]; 4]) -> Mat4 {
+        Mat4 { m }
+    }
+
+    pub fn identity() -> Mat4 {
+        Mat4 {
+            m: [
+                [1., 0., 0., 0.],
+                [0., 1., 0., 0.],
+                [0., 0., 1., 0.],
+                [0., 0., 0., 1.],
+            ],
+        }
+    }
+
+    pub fn translation(offset: Vec3) -> Mat4 {
+        let mut matrix = Mat4::identity();
+        matrix.m[0][3] = offset.x();
+        matrix.m[1][3] = offset.y();
+        matrix.m[2][3] = offset.z();
+        matrix
+    }
+
+    /// Scales every coordinate by the corresponding component of `factors`.
+    pub fn scaling(factors: Vec3) -> Mat4 {
+        let mut matrix = Mat4::identity();
+        matrix.m[0][0] = factors.x();
+        matrix.m[1][1] = factors.y();
+        matrix.m[2][2] = factors.z();
+        matrix
+    }
+
+    /// Counterclockwise rotation around `axis` (going through the origin) by `degrees`. Uses
+    /// Rodrigues' rotation formula.
+    pub fn rotation(axis: Vec3, degrees: f64) -> Mat4 {
+        let a = axis.unit();
+        let (x, y, z) = (a.x(), a.y(), a.z());
+        let (s, c) = math::sin_cos(degrees.to_radians());
+        let t = 1. - c;
+
+        Mat4 {
+            m: [
+                [t * x * x + c, t * x * y - s * z, t * x * z + s * y, 0.],
+                [t * x * y + s * z, t * y * y + c, t * y * z - s * x, 0.],
+                [t * x * z - s * y, t * y * z + s * x, t * z * z + c, 0.],
+                [0., 0., 0., 1.],
+            ],
+        }
+    }
+
+    pub fn transpose(&self) -> Mat4 {
+        let mut matrix = Mat4::identity();
+        for (i, row) in self.m.iter().enumerate() {
+            for (j, value) in row.iter().enumerate() {
+                matrix.m[j][i] = *value;
+            }
+        }
+        matrix
+    }
+
+    /// Returns `None` if the matrix is singular. Computed by Gauss-Jordan elimination with partial
+    /// pivoting.
+    pub fn inverse(&self) -> Option<Mat4> {
+        let mut a = self.m;
+        let mut inverse = Mat4::identity().m;
+
+        for column in 0..4 {
+            let pivot = (column..4)
+                .max_by(|&i, &j| a[i][column].abs().total_cmp(&a[j][column].abs()))
+                .unwrap();
+            if a[pivot][column].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(column, pivot);
+            inverse.swap(column, pivot);
+
+            let divisor = a[column][column];
+            for j in 0..4 {
+                a[column][j] /= divisor;
+                inverse[column][j] /= divisor;
+            }
+
+            for row in 0..4 {
+                if row == column {
+                    continue;
+                }
+                let factor = a[row][column];
+                for j in 0..4 {
+                    a[row][j] -= factor * a[column][j];
+                    inverse[row][j] -= factor * inverse[column][j];
+                }
+            }
+        }
+
+        Some(Mat4 { m: inverse })
+    }
+
+    /// Determinant of the upper-left 3×3 part, i.e. the linear part of the affine transformation
+    /// (how much the transformation scales volumes).
+    pub fn linear_determinant(&self) -> f64 {
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    /// Applies the whole transformation (including translation) to the point.
+    pub fn transform_point(&self, p: Point) -> Point {
+        let m = &self.m;
+        Point::new(
+            m[0][0] * p.x() + m[0][1] * p.y() + m[0][2] * p.z() + m[0][3],
+            m[1][0] * p.x() + m[1][1] * p.y() + m[1][2] * p.z() + m[1][3],
+            m[2][0] * p.x() + m[2][1] * p.y() + m[2][2] * p.z() + m[2][3],
+        )
+    }
+
+    /// Applies only the linear part of the transformation to the vector, vectors are not affected
+    /// by translation.
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * v.x() + m[0][1] * v.y() + m[0][2] * v.z(),
+            m[1][0] * v.x() + m[1][1] * v.y() + m[1][2] * v.z(),
+            m[2][0] * v.x() + m[2][1] * v.y() + m[2][2] * v.z(),
+        )
+    }
+}
+
+/// Composes transformations, `a * b` applies `b` first and then `a`.
+impl core::ops::Mul for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, rhs: Mat4) -> Mat4 {
+        let mut m = [[0.; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[i][k] * rhs.m[k][j]).sum();
+            }
+        }
+        Mat4 { m }
+    }
+}
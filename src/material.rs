@@ -1,77 +1,298 @@
-use std::marker::{Send, Sync};
+use alloc::boxed::Box;
+use core::marker::{Send, Sync};
 
 use crate::color::Color;
 use crate::hit_record::HitRecord;
+use crate::math;
 use crate::ray::Ray;
+use crate::texture::{SolidColor, Texture};
 use crate::vec3::Vec3;
 
+/// Default minimal distance of intersections with rays scattered from a surface.
+pub const DEFAULT_RAY_EPSILON: f64 = 0.001;
+
 pub trait Material: Send + Sync {
     /// Returns reflected ray and stores all necessary info about intersection into `rec`.
     fn scatter(&self, rec: &HitRecord, ray_in: &Ray) -> Option<Ray>;
-    /// Returns color of the material
-    fn attenuation(&self) -> Color;
+    /// Returns color of the material at the intersection `rec`
+    fn attenuation(&self, rec: &HitRecord) -> Color;
+    /// Returns attenuation of light coming from `direction` and reflected towards the origin of
+    /// `ray_in`. It differs from `attenuation` only for materials whose color depends on the
+    /// directions (e.g. `OrenNayar`). For diffuse materials, it is the BRDF multiplied by pi (the
+    /// cosine of the incoming light is applied by the caller).
+    fn reflectance(&self, rec: &HitRecord, _ray_in: &Ray, _direction: Vec3) -> Color {
+        self.attenuation(rec)
+    }
+    /// Returns color of the light emitted by the material at the intersection `rec` towards the
+    /// origin of `ray_in`. Most materials do not emit any light.
+    fn emitted(&self, _rec: &HitRecord, _ray_in: &Ray) -> Color {
+        Color::black()
+    }
+    /// Returns `true` if the material emits light, such objects are registered as scene lights.
+    fn is_emissive(&self) -> bool {
+        false
+    }
+    /// Returns minimal distance (in world units, not the parameter `t` of the possibly not
+    /// normalized scattered ray) of intersections with rays scattered from the surface. Prevents
+    /// the ray from hitting the surface it starts at due to rounding errors (shadow acne), too
+    /// large values make light leak through thin objects.
+    fn ray_epsilon(&self) -> f64 {
+        DEFAULT_RAY_EPSILON
+    }
+    /// Returns `true` if the material reflects light diffusely (as described by Lambert's cosine
+    /// law). Direct light is sampled explicitly (shadow rays) only for these materials.
+    fn is_diffuse(&self) -> bool {
+        false
+    }
+}
+
+/// Formulations of diffuse reflection the book goes through, for comparing how they look and
+/// converge.
+///
+/// More thorough explanation can be found at:
+/// https://raytracing.github.io/books/RayTracingInOneWeekend.html#diffusematerials
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DiffuseModel {
+    /// Normal plus a random point inside the unit sphere, the first approximation of the book.
+    /// Directions close to the normal are too frequent, grazing light is too weak.
+    UnitSphere,
+    /// Normal plus a random unit vector, which gives the true Lambertian (cosine) distribution.
+    /// Only this one matches the direct light of the shadow rays, surfaces with the other models
+    /// find lights by the scattered rays only.
+    UnitVector,
+    /// Directions uniformly distributed over the hemisphere around the normal, without the
+    /// cosine weighting.
+    Hemisphere,
+}
+
+impl DiffuseModel {
+    /// Returns a random direction (not normalized) of light reflected from the surface given by
+    /// unit `normal`.
+    pub fn direction(self, normal: Vec3) -> Vec3 {
+        match self {
+            DiffuseModel::UnitSphere => {
+                let direction = normal + Vec3::random_in_unit_sphere();
+                if direction.near_zero() {
+                    normal
+                } else {
+                    direction
+                }
+            }
+            DiffuseModel::UnitVector => diffuse_direction(normal),
+            DiffuseModel::Hemisphere => Vec3::random_in_hemisphere(normal),
+        }
+    }
 }
 
 /// Describes a material that is used to model diffused object surfaces
 pub struct Lambertian {
     /// How much light is reflected from the surface (as fraction)
-    albedo: Color,
+    albedo: Box<dyn Texture>,
+    model: DiffuseModel,
 }
 
 impl Lambertian {
     pub fn new(albedo: Color) -> Lambertian {
-        Lambertian { albedo }
+        Lambertian::textured(Box::new(SolidColor::new(albedo)))
+    }
+
+    /// The color of the surface is given by `albedo` texture.
+    pub fn textured(albedo: Box<dyn Texture>) -> Lambertian {
+        Lambertian {
+            albedo,
+            model: DiffuseModel::UnitVector,
+        }
+    }
+
+    /// Scatters the light by the given formulation instead of the default
+    /// `DiffuseModel::UnitVector`.
+    pub fn with_model(mut self, model: DiffuseModel) -> Lambertian {
+        self.model = model;
+        self
     }
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, rec: &HitRecord, _ray_in: &Ray) -> Option<Ray> {
-        // Random unit vector is a behaviour of the material
-        let mut direction = rec.normal + Vec3::random_unit_vector();
+    fn scatter(&self, rec: &HitRecord, ray_in: &Ray) -> Option<Ray> {
+        Some(scattered_ray(rec, ray_in, self.model.direction(rec.normal)))
+    }
+
+    fn attenuation(&self, rec: &HitRecord) -> Color {
+        self.albedo.value(rec.u, rec.v, rec.point)
+    }
+
+    /// Only the cosine distribution of `DiffuseModel::UnitVector` agrees with the direct light of
+    /// the shadow rays.
+    fn is_diffuse(&self) -> bool {
+        self.model == DiffuseModel::UnitVector
+    }
+}
+
+/// Diffuse material of rough surfaces (e.g. concrete, clay or the Moon), which are made of tiny
+/// Lambertian facets. Unlike `Lambertian`, it gets brighter when the light comes from behind the
+/// observer (retroreflection) and looks flatter at grazing angles.
+///
+/// Uses the qualitative model from "Generalization of Lambert's Reflectance Model" by Oren and
+/// Nayar, https://www1.cs.columbia.edu/CAVE/publications/pdfs/Oren_SIGGRAPH94.pdf
+pub struct OrenNayar {
+    albedo: Box<dyn Texture>,
+    /// Coefficients of the model derived from the roughness
+    a: f64,
+    b: f64,
+}
 
-        if direction.near_zero() {
-            direction = rec.normal;
+impl OrenNayar {
+    /// `sigma` is the roughness of the surface, i.e. the standard deviation of the angle of the
+    /// facets in degrees. Zero gives the `Lambertian` material.
+    pub fn new(albedo: Color, sigma: f64) -> OrenNayar {
+        OrenNayar::textured(Box::new(SolidColor::new(albedo)), sigma)
+    }
+
+    /// The color of the surface is given by `albedo` texture.
+    pub fn textured(albedo: Box<dyn Texture>, sigma: f64) -> OrenNayar {
+        let sigma_squared = sigma.to_radians() * sigma.to_radians();
+        OrenNayar {
+            albedo,
+            a: 1. - 0.5 * sigma_squared / (sigma_squared + 0.33),
+            b: 0.45 * sigma_squared / (sigma_squared + 0.09),
         }
+    }
+}
+
+impl Material for OrenNayar {
+    /// Directions are sampled by the cosine law as for `Lambertian`, the difference is accounted
+    /// for by `reflectance`.
+    fn scatter(&self, rec: &HitRecord, ray_in: &Ray) -> Option<Ray> {
+        Some(scattered_ray(rec, ray_in, diffuse_direction(rec.normal)))
+    }
+
+    fn attenuation(&self, rec: &HitRecord) -> Color {
+        self.albedo.value(rec.u, rec.v, rec.point)
+    }
+
+    fn reflectance(&self, rec: &HitRecord, ray_in: &Ray, direction: Vec3) -> Color {
+        let to_viewer = -ray_in.direction().unit();
+        let to_light = direction.unit();
+        // The side of the surface facing the viewer reflects the light
+        let normal = if Vec3::dot(rec.normal, to_viewer) < 0. {
+            -rec.normal
+        } else {
+            rec.normal
+        };
+        let cos_view = Vec3::dot(normal, to_viewer).clamp(0., 1.);
+        let cos_light = Vec3::dot(normal, to_light).clamp(0., 1.);
+
+        // Cosine of the azimuthal angle between the directions projected onto the surface
+        let view_tangent = to_viewer - cos_view * &normal;
+        let light_tangent = to_light - cos_light * &normal;
+        let cos_azimuth = if view_tangent.near_zero() || light_tangent.near_zero() {
+            0.
+        } else {
+            Vec3::dot(view_tangent.unit(), light_tangent.unit())
+        };
 
-        let new_ray = Ray::new(rec.point, direction);
-        Some(new_ray)
+        // sin(alpha) * tan(beta), alpha is the larger of the angles from the normal, beta the smaller
+        let (cos_alpha, cos_beta) = if cos_view < cos_light {
+            (cos_view, cos_light)
+        } else {
+            (cos_light, cos_view)
+        };
+        let sin_alpha = math::sqrt(1. - cos_alpha * cos_alpha);
+        let tan_beta = math::sqrt(1. - cos_beta * cos_beta) / cos_beta.max(1e-6);
+
+        (self.a + self.b * cos_azimuth.max(0.) * sin_alpha * tan_beta) * self.attenuation(rec)
     }
 
-    fn attenuation(&self) -> Color {
-        self.albedo.copy()
+    fn is_diffuse(&self) -> bool {
+        true
     }
 }
 
 /// There are two `Metal` materials, one of them is shiny and the other is fuzzy.
 pub struct Metal {
-    albedo: Color,
+    /// Color of the reflection, a texture allows patterned metals (e.g. a logo on a reflective
+    /// surface)
+    albedo: Box<dyn Texture>,
+    /// Roughness of the surface from 0 (mirror) to 1. Reflected rays are spread over a cone around
+    /// the ideal reflection, whose half-angle is `fuzz * 90°`.
     fuzz: f64,
 }
 
 impl Metal {
     pub fn fuzzy(albedo: Color, fuzz: f64) -> Metal {
+        Metal::textured(Box::new(SolidColor::new(albedo)), fuzz)
+    }
+
+    pub fn shiny(albedo: Color) -> Metal {
+        Metal::fuzzy(albedo, 0.)
+    }
+
+    /// The color of the reflection is given by `albedo` texture.
+    pub fn textured(albedo: Box<dyn Texture>, fuzz: f64) -> Metal {
         let fuzz = if fuzz < 1. { fuzz } else { 1. };
         Metal { albedo, fuzz }
     }
+}
 
-    pub fn shiny(albedo: Color) -> Metal {
-        Metal { albedo, fuzz: 0. }
+/// Returns a random direction (not normalized) of light reflected diffusely from the surface given
+/// by unit `normal`, distributed according to Lambert's cosine law. A sample that (almost) cancels
+/// out the normal is replaced by the normal itself, so the direction is never degenerate.
+///
+/// More thorough explanation can be found at:
+/// https://raytracing.github.io/books/RayTracingInOneWeekend.html#diffusematerials/truelambertianreflection
+pub fn diffuse_direction(normal: Vec3) -> Vec3 {
+    let direction = normal + Vec3::random_unit_vector();
+    if direction.near_zero() {
+        normal
+    } else {
+        direction
     }
 }
 
+/// Returns the ray leaving the intersection `rec` in `direction`, at the time of the incoming ray.
+fn scattered_ray(rec: &HitRecord, ray_in: &Ray, direction: Vec3) -> Ray {
+    Ray::new_at_time(rec.point, direction, ray_in.time())
+}
+
 /// Reflects vector `v` from surface given by `normal` vector
-fn reflect(v: Vec3, normal: Vec3) -> Vec3 {
+pub fn reflect(v: Vec3, normal: Vec3) -> Vec3 {
     let b = Vec3::dot(v, normal);
     v - 2. * b * &normal
 }
 
+/// Refracts unit vector `uv` passing through the surface given by unit `normal` (pointing against
+/// `uv`) according to Snell's law. `ratio` is the ratio of the refractive indices of the medium the
+/// ray comes from and the medium it enters. Returns `None` in case of total internal reflection.
+///
+/// More thorough explanation can be found at:
+/// https://raytracing.github.io/books/RayTracingInOneWeekend.html#dielectrics/snell'slaw
+pub fn refract(uv: Vec3, normal: Vec3, ratio: f64) -> Option<Vec3> {
+    let cos_theta = Vec3::dot(-uv, normal).min(1.);
+    let sin_theta = math::sqrt(1. - cos_theta * cos_theta);
+    if ratio * sin_theta > 1. {
+        return None;
+    }
+
+    let perpendicular = ratio * &(uv + cos_theta * &normal);
+    let parallel = -math::sqrt((1. - perpendicular.length_squared()).abs()) * &normal;
+    Some(perpendicular + parallel)
+}
+
+/// Ratio of refractive indices for `refract`. A ray hitting the front face of an object with
+/// refractive index `ior` comes from the air (index 1), otherwise it leaves the object.
+pub fn refraction_ratio(front_face: bool, ior: f64) -> f64 {
+    if front_face {
+        1. / ior
+    } else {
+        ior
+    }
+}
+
 impl Material for Metal {
     fn scatter(&self, rec: &HitRecord, ray_in: &Ray) -> Option<Ray> {
-        let reflected = reflect(ray_in.direction().unit_vector(), rec.normal);
-        let scattered = Ray::new(
-            rec.point,
-            reflected + self.fuzz * &Vec3::random_unit_vector(),
-        );
+        let reflected = reflect(ray_in.direction().unit(), rec.normal);
+        let cos_max = math::cos(self.fuzz * core::f64::consts::FRAC_PI_2);
+        let scattered = scattered_ray(rec, ray_in, Vec3::random_in_cone(reflected, cos_max));
         if Vec3::dot(scattered.direction(), rec.normal) > 0. {
             Some(scattered)
         } else {
@@ -79,7 +300,198 @@ impl Material for Metal {
         }
     }
 
-    fn attenuation(&self) -> Color {
-        self.albedo.copy()
+    fn attenuation(&self, rec: &HitRecord) -> Color {
+        self.albedo.value(rec.u, rec.v, rec.point)
+    }
+
+    /// Fuzzy reflections leave the surface at grazing angles, where rounding errors of the
+    /// intersection move the origin of the ray further below the surface, so the offset grows with
+    /// `fuzz` (up to ten times the default for the roughest metal).
+    fn ray_epsilon(&self) -> f64 {
+        DEFAULT_RAY_EPSILON * (1. + 9. * self.fuzz)
+    }
+}
+
+/// Describes a material that emits light uniformly in all directions and does not reflect any.
+pub struct DiffuseLight {
+    emit: Box<dyn Texture>,
+    intensity: f64,
+}
+
+impl DiffuseLight {
+    /// Light colors are not limited to range from 0 to 1, therefore `color` is multiplied by
+    /// `intensity`.
+    pub fn new(color: Color, intensity: f64) -> DiffuseLight {
+        DiffuseLight::textured(Box::new(SolidColor::new(color)), intensity)
+    }
+
+    /// The color of the emitted light is given by `emit` texture (e.g. a screen or a stained glass
+    /// window) multiplied by `intensity`.
+    pub fn textured(emit: Box<dyn Texture>, intensity: f64) -> DiffuseLight {
+        DiffuseLight { emit, intensity }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _rec: &HitRecord, _ray_in: &Ray) -> Option<Ray> {
+        None
+    }
+
+    fn attenuation(&self, _rec: &HitRecord) -> Color {
+        Color::black()
+    }
+
+    fn emitted(&self, rec: &HitRecord, _ray_in: &Ray) -> Color {
+        self.intensity * self.emit.value(rec.u, rec.v, rec.point)
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+}
+
+/// Describes a material that emits light into a cone around the `direction`. The light has full
+/// intensity inside of the inner cone and smoothly falls off to zero at the edge of the outer cone.
+pub struct SpotLight {
+    emit: Color,
+    direction: Vec3,
+    cos_inner: f64,
+    cos_outer: f64,
+}
+
+impl SpotLight {
+    /// Angles are half-angles of the cones in degrees, `outer_angle` is expected to be larger than
+    /// `inner_angle`.
+    pub fn new(
+        color: Color,
+        intensity: f64,
+        direction: Vec3,
+        inner_angle: f64,
+        outer_angle: f64,
+    ) -> SpotLight {
+        SpotLight {
+            emit: intensity * color,
+            direction: direction.unit(),
+            cos_inner: math::cos(inner_angle.to_radians()),
+            cos_outer: math::cos(outer_angle.to_radians()),
+        }
+    }
+}
+
+impl Material for SpotLight {
+    fn scatter(&self, _rec: &HitRecord, _ray_in: &Ray) -> Option<Ray> {
+        None
+    }
+
+    fn attenuation(&self, _rec: &HitRecord) -> Color {
+        Color::black()
+    }
+
+    /// The intensity depends on the angle between the light direction and the direction towards
+    /// the observer (opposite to the incoming ray).
+    fn emitted(&self, _rec: &HitRecord, ray_in: &Ray) -> Color {
+        let cosine = Vec3::dot(self.direction, -ray_in.direction().unit());
+        if cosine <= self.cos_outer {
+            return Color::black();
+        }
+        if cosine >= self.cos_inner {
+            return self.emit.copy();
+        }
+
+        // Smoothstep between the outer and the inner cone
+        let t = (cosine - self.cos_outer) / (self.cos_inner - self.cos_outer);
+        (t * t * (3. - 2. * t)) * self.emit.copy()
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit_at(point: Vec3, normal: Vec3) -> HitRecord {
+        let mut rec = HitRecord::new();
+        rec.point = point;
+        rec.normal = normal;
+        rec
+    }
+
+    #[test]
+    fn lambertian_scatters_from_the_hit_point_into_the_hemisphere() {
+        crate::random::seed(1, 0);
+        let material = Lambertian::new(Color::new(0.5, 0.5, 0.5));
+        let normal = Vec3::new(0., 1., 0.);
+        let rec = hit_at(Vec3::new(1., 2., 3.), normal);
+        let ray_in = Ray::new_at_time(Vec3::new(1., 5., 3.), Vec3::new(0., -1., 0.), 0.25);
+
+        let count = 10_000;
+        let mut cosines = 0.;
+        for _ in 0..count {
+            let scattered = material.scatter(&rec, &ray_in).unwrap();
+            assert!(scattered.origin().approx_eq(&rec.point, 0.));
+            assert_eq!(scattered.time(), 0.25);
+            let direction = scattered.direction();
+            assert!(!direction.near_zero());
+            let cosine = Vec3::dot(direction.unit(), normal);
+            assert!(cosine >= 0.);
+            cosines += cosine;
+        }
+        // The mean cosine of directions distributed by Lambert's law is 2/3
+        assert!((cosines / count as f64 - 2. / 3.).abs() < 0.01);
+    }
+
+    #[test]
+    fn only_cosine_model_samples_lights_directly() {
+        let gray = || Lambertian::new(Color::new(0.5, 0.5, 0.5));
+        assert!(gray().is_diffuse());
+        assert!(gray().with_model(DiffuseModel::UnitVector).is_diffuse());
+        assert!(!gray().with_model(DiffuseModel::UnitSphere).is_diffuse());
+        assert!(!gray().with_model(DiffuseModel::Hemisphere).is_diffuse());
+    }
+
+    #[test]
+    fn ray_at_45_degrees_bends_into_glass_by_snells_law() {
+        let angle = core::f64::consts::FRAC_PI_4;
+        let incoming = Vec3::new(math::sin(angle), -math::cos(angle), 0.);
+        let normal = Vec3::new(0., 1., 0.);
+        let refracted = refract(incoming, normal, refraction_ratio(true, 1.5)).unwrap();
+
+        // sin(45°) = 1.5 sin(theta)
+        let sin_theta = math::sin(angle) / 1.5;
+        let cos_theta = math::sqrt(1. - sin_theta * sin_theta);
+        assert!(refracted.approx_eq(&Vec3::new(sin_theta, -cos_theta, 0.), 1e-12));
+        assert!((refracted.length() - 1.).abs() < 1e-12);
+    }
+
+    #[test]
+    fn ray_leaving_glass_at_45_degrees_is_totally_reflected() {
+        let angle = core::f64::consts::FRAC_PI_4;
+        let incoming = Vec3::new(math::sin(angle), -math::cos(angle), 0.);
+        let normal = Vec3::new(0., 1., 0.);
+        // The critical angle of glass is about 41.8°
+        assert!(refract(incoming, normal, refraction_ratio(false, 1.5)).is_none());
+        assert!(refract(incoming, normal, refraction_ratio(false, 1.3)).is_some());
+    }
+
+    #[test]
+    fn near_zero_needs_all_components_small() {
+        assert!(Vec3::zero().near_zero());
+        assert!(Vec3::new(1e-9, -1e-9, 0.).near_zero());
+        assert!(!Vec3::new(1e-9, 1e-3, 0.).near_zero());
+        assert!(!Vec3::new(-1., 0., 0.).near_zero());
+    }
+
+    #[test]
+    fn diffuse_direction_is_never_degenerate() {
+        crate::random::seed(1, 1);
+        let normal = Vec3::new(0., 0., -1.);
+        for _ in 0..10_000 {
+            let direction = diffuse_direction(normal);
+            assert!(!direction.near_zero());
+            assert!(Vec3::dot(direction, normal) >= 0.);
+        }
     }
 }
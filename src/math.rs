@@ -0,0 +1,79 @@
+//! Floating point functions which are not available in `core` (the standard library implements
+//! them by the platform's math library). The crate calls them only through this module, without
+//! the `std` feature they are computed by `libm`.
+
+#[cfg(not(feature = "std"))]
+pub use libm::{acos, atan, atan2, cbrt, ceil, cos, exp, pow as powf, round, sin, sqrt, tan};
+
+#[cfg(feature = "std")]
+pub fn sqrt(x: f64) -> f64 {
+    x.sqrt()
+}
+
+#[cfg(feature = "std")]
+pub fn cbrt(x: f64) -> f64 {
+    x.cbrt()
+}
+
+#[cfg(feature = "std")]
+pub fn powf(x: f64, n: f64) -> f64 {
+    x.powf(n)
+}
+
+#[cfg(feature = "std")]
+pub fn exp(x: f64) -> f64 {
+    x.exp()
+}
+
+#[cfg(feature = "std")]
+pub fn sin(x: f64) -> f64 {
+    x.sin()
+}
+
+#[cfg(feature = "std")]
+pub fn cos(x: f64) -> f64 {
+    x.cos()
+}
+
+#[cfg(feature = "std")]
+pub fn tan(x: f64) -> f64 {
+    x.tan()
+}
+
+#[cfg(feature = "std")]
+pub fn acos(x: f64) -> f64 {
+    x.acos()
+}
+
+#[cfg(feature = "std")]
+pub fn atan(x: f64) -> f64 {
+    x.atan()
+}
+
+/// Angle of the point `(x, y)` from the positive X axis, from -pi to pi.
+#[cfg(feature = "std")]
+pub fn atan2(y: f64, x: f64) -> f64 {
+    y.atan2(x)
+}
+
+#[cfg(feature = "std")]
+pub fn ceil(x: f64) -> f64 {
+    x.ceil()
+}
+
+#[cfg(feature = "std")]
+pub fn round(x: f64) -> f64 {
+    x.round()
+}
+
+/// Returns `(sin(x), cos(x))`.
+#[cfg(feature = "std")]
+pub fn sin_cos(x: f64) -> (f64, f64) {
+    x.sin_cos()
+}
+
+/// Returns `(sin(x), cos(x))`.
+#[cfg(not(feature = "std"))]
+pub fn sin_cos(x: f64) -> (f64, f64) {
+    libm::sincos(x)
+}
@@ -1,6 +1,14 @@
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use crate::aabb::Aabb;
 use crate::color::Color;
 use crate::hit_record::HitRecord;
+use crate::mat4::Mat4;
 use crate::material::Material;
+use crate::math;
+use crate::random;
 use crate::ray::Ray;
 use crate::vec3::Vec3;
 use crate::vec3::Vec3 as Point;
@@ -20,23 +28,70 @@ impl Sphere {
             material,
         }
     }
+
+    /// Sphere with radius 1 centered at the origin (e.g. to be placed by `Transform`).
+    pub fn unit(material: Box<dyn Material>) -> Sphere {
+        Sphere::new(Point::zero(), 1., material)
+    }
+
+    /// Sphere centered at the point `(x, y, z)`.
+    pub fn at(x: f64, y: f64, z: f64, radius: f64, material: Box<dyn Material>) -> Sphere {
+        Sphere::new(Point::new(x, y, z), radius, material)
+    }
+
+    pub fn center(&self) -> Point {
+        self.center
+    }
+
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    /// Returns texture coordinates `(u, v)` of the `point` on the surface, the same as the hits
+    /// get. Points off the surface are projected onto it from the center. E.g. the bottom pole is
+    /// `(_, 0)`, the top pole `(_, 1)` and the equator at -X, +Z, +X and -Z is at `u` 0, 0.25, 0.5
+    /// and 0.75 (`v` is 0.5), as in RTIOW.
+    pub fn uv(&self, point: Point) -> (f64, f64) {
+        sphere_uv((point - self.center).unit())
+    }
 }
 
 impl Material for Sphere {
     fn scatter(&self, rec: &HitRecord, ray_in: &Ray) -> Option<Ray> {
-        self.material.scatter(&rec, ray_in)
+        self.material.scatter(rec, ray_in)
+    }
+
+    fn attenuation(&self, rec: &HitRecord) -> Color {
+        self.material.attenuation(rec)
+    }
+
+    fn reflectance(&self, rec: &HitRecord, ray_in: &Ray, direction: Vec3) -> Color {
+        self.material.reflectance(rec, ray_in, direction)
+    }
+
+    fn emitted(&self, rec: &HitRecord, ray_in: &Ray) -> Color {
+        self.material.emitted(rec, ray_in)
     }
 
-    fn attenuation(&self) -> Color {
-        self.material.attenuation()
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn is_diffuse(&self) -> bool {
+        self.material.is_diffuse()
+    }
+
+    fn ray_epsilon(&self) -> f64 {
+        self.material.ray_epsilon()
     }
 }
 
 impl Hittable for Sphere {
     /// The intersection is a solution of a quadratic equation describing a spacial geometry of ray
-    /// and sphere. If it has one root, the ray only touches the sphere (ignored for simplicity
-    /// reasons), if it has two roots, it intersects the sphere, therefore `true` is returned and
-    /// `HitRecord` is set.
+    /// and sphere. If it has one root (zero discriminant), the ray only touches the sphere and
+    /// `false` is returned: the normal at such a point is perpendicular to the ray, the hit would
+    /// contribute nothing but a degenerate shading point at the silhouette. If it has two roots,
+    /// it intersects the sphere, therefore `true` is returned and `HitRecord` is set.
     ///
     /// More thorough explanation can be found at:
     /// https://raytracing.github.io/books/RayTracingInOneWeekend.html#addingasphere
@@ -48,13 +103,15 @@ impl Hittable for Sphere {
         let c = Vec3::dot(oc, oc) - self.radius * self.radius;
 
         let discriminant = b * b - 4. * a * c;
-        if discriminant < 0.0 {
+        // Tangent rays are rejected explicitly, otherwise both roots would be the same point
+        if discriminant <= 0.0 {
             return false;
         }
 
-        let mut root = (-b - discriminant.sqrt()) / (2.0 * a);
+        let sqrt_discriminant = math::sqrt(discriminant);
+        let mut root = (-b - sqrt_discriminant) / (2.0 * a);
         if root < t_min || t_max < root {
-            root = (-b + discriminant.sqrt()) / (2.0 * a);
+            root = (-b + sqrt_discriminant) / (2.0 * a);
             if root < t_min || t_max < root {
                 return false;
             }
@@ -63,9 +120,836 @@ impl Hittable for Sphere {
         rec.t = root;
         rec.point = ray.at(rec.t);
         rec.normal = (rec.point - self.center) / self.radius;
+        (rec.u, rec.v) = sphere_uv(rec.normal);
+        let (tangent, bitangent) = sphere_tangents(rec.normal);
+        rec.tangent = Some(tangent);
+        rec.bitangent = Some(bitangent);
 
         true
     }
+
+    fn can_be_sampled(&self) -> bool {
+        true
+    }
+
+    /// Picks a point visible from `origin` by sampling directions uniformly from the cone the
+    /// sphere subtends, which is much less noisy than sampling the whole surface (half of it is
+    /// hidden behind the sphere itself). From inside the sphere, the whole surface is visible and
+    /// the point is picked uniformly from it.
+    ///
+    /// More thorough explanation can be found at:
+    /// https://raytracing.github.io/books/RayTracingTheRestOfYourLife.html#samplinglightsdirectly
+    fn random_point(&self, origin: Point) -> Point {
+        let to_center = self.center - origin;
+        let distance_squared = to_center.length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return self.center + self.radius * &Vec3::random_unit_vector();
+        }
+
+        let cos_max = math::sqrt(1. - radius_squared / distance_squared);
+        let direction = Vec3::random_in_cone(to_center.unit(), cos_max);
+        // Nearer intersection of the ray from `origin` with the sphere, rounding errors at the edge
+        // of the cone are treated as touching the sphere.
+        let projection = Vec3::dot(direction, to_center);
+        let half_chord =
+            math::sqrt((radius_squared - (distance_squared - projection * projection)).max(0.));
+        origin + (projection - half_chord) * &direction
+    }
+
+    /// Probability density of the direction from `origin` (solid angle) generated by
+    /// `random_point`, i.e. 1 / solid angle of the cone the sphere subtends from outside, or the
+    /// density of picking a point on the surface (1 / area) converted to solid angle from inside.
+    fn pdf_value(&self, origin: Point, direction: Vec3) -> f64 {
+        let mut rec = HitRecord::new();
+        if !self.hit(&Ray::new(origin, direction), 0.001, f64::MAX, &mut rec) {
+            return 0.0;
+        }
+
+        let distance_squared = (self.center - origin).length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared > radius_squared {
+            let cos_max = math::sqrt(1. - radius_squared / distance_squared);
+            return 1. / (2. * core::f64::consts::PI * (1. - cos_max));
+        }
+
+        let area = 4.0 * core::f64::consts::PI * radius_squared;
+        let hit_distance_squared = (rec.point - origin).length_squared();
+        let cosine = Vec3::dot(rec.normal, direction.unit()).abs();
+
+        hit_distance_squared / (cosine * area)
+    }
+
+    /// Spheres with zero (or negative) radius, and spheres with non-finite parameters, have no
+    /// surface.
+    fn is_degenerate(&self) -> bool {
+        !(self.radius > 0.
+            && self.radius.is_finite()
+            && self.center.x().is_finite()
+            && self.center.y().is_finite()
+            && self.center.z().is_finite())
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let radius = Vec3::new(self.radius, self.radius, self.radius);
+        Some(Aabb::new(self.center - radius, self.center + radius))
+    }
+
+    fn contains(&self, point: Point) -> bool {
+        (point - self.center).length_squared() <= self.radius * self.radius
+    }
 }
 
 impl TraceableObjects for Sphere {}
+
+/// Returns texture coordinates of the point on a unit sphere (centered at the origin) given by
+/// outward unit `normal`. `u` is the angle around the Y axis starting at X=-1, `v` is the angle
+/// from Y=-1 to Y=+1, both scaled to range from 0 to 1. Normals computed from hit points can be
+/// slightly longer than 1, `y` is clamped so that the poles do not get NaN.
+///
+/// More thorough explanation can be found at:
+/// https://raytracing.github.io/books/RayTracingTheNextWeek.html#texturemapping/texturecoordinatesforspheres
+fn sphere_uv(normal: Vec3) -> (f64, f64) {
+    let theta = math::acos((-normal.y()).clamp(-1., 1.));
+    let phi = math::atan2(-normal.z(), normal.x()) + core::f64::consts::PI;
+    (
+        phi / (2. * core::f64::consts::PI),
+        theta / core::f64::consts::PI,
+    )
+}
+
+/// Returns unit tangent and bitangent at the point of a unit sphere given by outward unit `normal`,
+/// i.e. directions of increasing `u` and `v` of `sphere_uv`. The tangent is undefined at the poles,
+/// where X axis is used instead.
+fn sphere_tangents(normal: Vec3) -> (Vec3, Vec3) {
+    // Derivative of the point by `phi` is (sin(phi) sin(theta), 0, cos(phi) sin(theta))
+    let tangent = Vec3::new(normal.z(), 0., -normal.x());
+    let tangent = if tangent.near_zero() {
+        Vec3::new(1., 0., 0.)
+    } else {
+        tangent.unit()
+    };
+    (tangent, Vec3::cross(normal, tangent))
+}
+
+/// Cylinder with hemispherical caps (a rounded rod), given by the centers of the caps and the
+/// radius. `u` goes around the axis, `v` along it from the outer end of the first cap to the
+/// outer end of the second one.
+pub struct Capsule {
+    start: Point,
+    end: Point,
+    radius: f64,
+    material: Box<dyn Material>,
+}
+
+impl Capsule {
+    /// If `start` and `end` are the same point, the capsule is a sphere.
+    pub fn new(start: Point, end: Point, radius: f64, material: Box<dyn Material>) -> Capsule {
+        Capsule {
+            start,
+            end,
+            radius,
+            material,
+        }
+    }
+
+    /// Returns the point on the axis (between the centers of the caps) nearest to `point`.
+    fn nearest_on_axis(&self, point: Point) -> Point {
+        let axis = self.end - self.start;
+        let length_squared = axis.length_squared();
+        if length_squared == 0. {
+            return self.start;
+        }
+        let t = (Vec3::dot(point - self.start, axis) / length_squared).clamp(0., 1.);
+        self.start + t * &axis
+    }
+
+    /// Unit direction of the axis (+Y for a sphere) and two unit directions perpendicular to it
+    /// and to each other, from which the angle around the axis is measured.
+    fn axis_frame(&self) -> (Vec3, Vec3, Vec3) {
+        let axis = self.end - self.start;
+        let length = axis.length();
+        let axis_direction = if length > 0. {
+            axis / length
+        } else {
+            Vec3::new(0., 1., 0.)
+        };
+        // An arbitrary direction perpendicular to the axis
+        let reference = if axis_direction.x().abs() > 0.9 {
+            Vec3::new(0., 1., 0.)
+        } else {
+            Vec3::new(1., 0., 0.)
+        };
+        let first = Vec3::cross(axis_direction, reference).unit();
+        let second = Vec3::cross(axis_direction, first);
+        (axis_direction, first, second)
+    }
+
+    /// Areas of the cylindrical part and of both caps together (a whole sphere).
+    fn areas(&self) -> (f64, f64) {
+        let length = (self.end - self.start).length();
+        (
+            2. * core::f64::consts::PI * self.radius * length,
+            4. * core::f64::consts::PI * self.radius * self.radius,
+        )
+    }
+
+    /// Roots of the ray with the infinite cylinder around the axis, `None` if the ray is parallel
+    /// to the axis or misses the cylinder.
+    fn cylinder_roots(&self, ray: &Ray) -> Option<(f64, f64)> {
+        let axis = self.end - self.start;
+        let length_squared = axis.length_squared();
+        if length_squared == 0. {
+            return None;
+        }
+        // Components of the direction and the origin perpendicular to the axis
+        let perpendicular = |v: Vec3| v - (Vec3::dot(v, axis) / length_squared) * &axis;
+        let direction = perpendicular(ray.direction());
+        let oc = perpendicular(ray.origin() - self.start);
+        quadratic_roots(
+            Vec3::dot(direction, direction),
+            2. * Vec3::dot(direction, oc),
+            Vec3::dot(oc, oc) - self.radius * self.radius,
+        )
+    }
+}
+
+/// Both roots of `a t² + b t + c = 0` (smaller first), `None` if there are less than two of them.
+fn quadratic_roots(a: f64, b: f64, c: f64) -> Option<(f64, f64)> {
+    let discriminant = b * b - 4. * a * c;
+    if a == 0. || discriminant <= 0. {
+        return None;
+    }
+    let sqrt_discriminant = math::sqrt(discriminant);
+    Some((
+        (-b - sqrt_discriminant) / (2. * a),
+        (-b + sqrt_discriminant) / (2. * a),
+    ))
+}
+
+impl Material for Capsule {
+    fn scatter(&self, rec: &HitRecord, ray_in: &Ray) -> Option<Ray> {
+        self.material.scatter(rec, ray_in)
+    }
+
+    fn attenuation(&self, rec: &HitRecord) -> Color {
+        self.material.attenuation(rec)
+    }
+
+    fn reflectance(&self, rec: &HitRecord, ray_in: &Ray, direction: Vec3) -> Color {
+        self.material.reflectance(rec, ray_in, direction)
+    }
+
+    fn emitted(&self, rec: &HitRecord, ray_in: &Ray) -> Color {
+        self.material.emitted(rec, ray_in)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn is_diffuse(&self) -> bool {
+        self.material.is_diffuse()
+    }
+
+    fn ray_epsilon(&self) -> f64 {
+        self.material.ray_epsilon()
+    }
+}
+
+impl Hittable for Capsule {
+    /// The surface consists of the part of the infinite cylinder between the caps and of the
+    /// outer halves of the spheres around the caps. Roots of the ray with all three quadrics are
+    /// collected and the nearest one lying on its part of the surface is the hit. The parts
+    /// overlap at the seams (both tests include the boundary), so rays there do not slip through
+    /// rounding errors.
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let axis = self.end - self.start;
+        let length_squared = axis.length_squared();
+        // Position of the point along the axis, 0 at `start` and `length_squared` at `end`
+        let along = |t: f64| Vec3::dot(ray.at(t) - self.start, axis);
+
+        let sphere_roots = |center: Point| {
+            let oc = ray.origin() - center;
+            quadratic_roots(
+                Vec3::dot(ray.direction(), ray.direction()),
+                2. * Vec3::dot(ray.direction(), oc),
+                Vec3::dot(oc, oc) - self.radius * self.radius,
+            )
+        };
+        let body = self
+            .cylinder_roots(ray)
+            .map(|(t1, t2)| [t1, t2])
+            .into_iter()
+            .flatten()
+            .filter(|&t| (0. ..=length_squared).contains(&along(t)));
+        let start_cap = sphere_roots(self.start)
+            .map(|(t1, t2)| [t1, t2])
+            .into_iter()
+            .flatten()
+            .filter(|&t| along(t) <= 0.);
+        let end_cap = sphere_roots(self.end)
+            .map(|(t1, t2)| [t1, t2])
+            .into_iter()
+            .flatten()
+            .filter(|&t| along(t) >= length_squared);
+
+        let nearest = body
+            .chain(start_cap)
+            .chain(end_cap)
+            .filter(|&t| t_min <= t && t <= t_max)
+            .min_by(f64::total_cmp);
+        let Some(t) = nearest else {
+            return false;
+        };
+
+        rec.t = t;
+        rec.point = ray.at(t);
+        rec.normal = (rec.point - self.nearest_on_axis(rec.point)) / self.radius;
+
+        let length = math::sqrt(length_squared);
+        let (axis_direction, first, second) = self.axis_frame();
+        let phi = math::atan2(Vec3::dot(rec.normal, second), Vec3::dot(rec.normal, first))
+            + core::f64::consts::PI;
+        rec.u = phi / (2. * core::f64::consts::PI);
+        rec.v = ((Vec3::dot(rec.point - self.start, axis_direction) + self.radius)
+            / (length + 2. * self.radius))
+            .clamp(0., 1.);
+        let tangent = Vec3::cross(axis_direction, rec.normal);
+        if tangent.near_zero() {
+            rec.tangent = None;
+            rec.bitangent = None;
+        } else {
+            let tangent = tangent.unit();
+            rec.tangent = Some(tangent);
+            rec.bitangent = Some(Vec3::cross(rec.normal, tangent));
+        }
+
+        true
+    }
+
+    fn can_be_sampled(&self) -> bool {
+        true
+    }
+
+    /// Picks a point uniformly from the whole surface, the cylindrical part or the caps in
+    /// proportion to their areas. Points on the far side are hidden by the capsule itself, shadow
+    /// rays aimed at them are blocked.
+    fn random_point(&self, _origin: Point) -> Point {
+        let (cylinder_area, caps_area) = self.areas();
+        if random::random_range(0., cylinder_area + caps_area) < cylinder_area {
+            let (_, first, second) = self.axis_frame();
+            let along = random::random_range(0., 1.);
+            let phi = random::random_range(0., 2. * core::f64::consts::PI);
+            let (sin, cos) = math::sin_cos(phi);
+            let around = cos * &first + sin * &second;
+            self.start + along * &(self.end - self.start) + self.radius * &around
+        } else {
+            // Outer halves of the spheres around the caps together form a whole sphere
+            let normal = Vec3::random_unit_vector();
+            let cap = if Vec3::dot(normal, self.end - self.start) > 0. {
+                self.end
+            } else {
+                self.start
+            };
+            cap + self.radius * &normal
+        }
+    }
+
+    /// Probability density of the direction from `origin` (solid angle) generated by
+    /// `random_point`, the density of picking the nearest visible point (1 / area) converted to
+    /// solid angle.
+    fn pdf_value(&self, origin: Point, direction: Vec3) -> f64 {
+        let mut rec = HitRecord::new();
+        if !self.hit(&Ray::new(origin, direction), 0.001, f64::MAX, &mut rec) {
+            return 0.0;
+        }
+
+        let (cylinder_area, caps_area) = self.areas();
+        let hit_distance_squared = (rec.point - origin).length_squared();
+        let cosine = Vec3::dot(rec.normal, direction.unit()).abs();
+
+        hit_distance_squared / (cosine * (cylinder_area + caps_area))
+    }
+
+    /// Capsules with zero (or negative) radius, and capsules with non-finite parameters, have no
+    /// surface.
+    fn is_degenerate(&self) -> bool {
+        !(self.radius > 0.
+            && self.radius.is_finite()
+            && self.start.is_finite()
+            && self.end.is_finite())
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let radius = Vec3::new(self.radius, self.radius, self.radius);
+        Some(Aabb::new(
+            Vec3::component_min(self.start, self.end) - radius,
+            Vec3::component_max(self.start, self.end) + radius,
+        ))
+    }
+
+    fn contains(&self, point: Point) -> bool {
+        (point - self.nearest_on_axis(point)).length_squared() <= self.radius * self.radius
+    }
+}
+
+impl TraceableObjects for Capsule {}
+
+/// Places the wrapped object into the scene using an affine transformation (any combination of
+/// translation, rotation and scaling). Rays are transformed into the object space, where the
+/// intersection is computed, and the results are transformed back to the world space.
+pub struct Transform {
+    object: Box<dyn TraceableObjects>,
+    placement: Placement,
+}
+
+impl Transform {
+    /// Returns `None` if the `matrix` cannot be inverted.
+    pub fn new(object: Box<dyn TraceableObjects>, matrix: Mat4) -> Option<Transform> {
+        Some(Transform {
+            object,
+            placement: Placement::new(matrix)?,
+        })
+    }
+}
+
+/// Affine transformation of an object into the world space together with the matrices derived
+/// from it, shared by `Transform` and `InstanceList`.
+struct Placement {
+    matrix: Mat4,
+    inverse: Mat4,
+    /// Normals are transformed by the inverse-transpose matrix, so that they stay perpendicular to
+    /// the surface even with non-uniform scaling.
+    normal_matrix: Mat4,
+}
+
+impl Placement {
+    /// Returns `None` if the `matrix` cannot be inverted.
+    fn new(matrix: Mat4) -> Option<Placement> {
+        let inverse = matrix.inverse()?;
+        Some(Placement {
+            matrix,
+            inverse,
+            normal_matrix: inverse.transpose(),
+        })
+    }
+
+    fn to_object_space(&self, ray: &Ray) -> Ray {
+        Ray::new_at_time(
+            self.inverse.transform_point(ray.origin()),
+            self.inverse.transform_vector(ray.direction()),
+            ray.time(),
+        )
+    }
+
+    /// The direction of the transformed ray is not normalized, therefore parameter `t` is the same
+    /// in both object and world space.
+    fn hit(
+        &self,
+        object: &dyn Hittable,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rec: &mut HitRecord,
+    ) -> bool {
+        if !object.hit(&self.to_object_space(ray), t_min, t_max, rec) {
+            return false;
+        }
+
+        rec.point = ray.at(rec.t);
+        rec.normal = self.normal_matrix.transform_vector(rec.normal).unit();
+        // Tangents lie in the surface, they are transformed like the surface itself
+        let to_world = |v: Vec3| self.matrix.transform_vector(v).unit();
+        rec.tangent = rec.tangent.map(to_world);
+        rec.bitangent = rec.bitangent.map(to_world);
+
+        true
+    }
+
+    fn random_point(&self, object: &dyn Hittable, origin: Point) -> Point {
+        let point = object.random_point(self.inverse.transform_point(origin));
+        self.matrix.transform_point(point)
+    }
+
+    /// Probability density of the direction in the object space is multiplied by the Jacobian of
+    /// the mapping between world and object space directions.
+    fn pdf_value(&self, object: &dyn Hittable, origin: Point, direction: Vec3) -> f64 {
+        let local_direction = self.inverse.transform_vector(direction.unit());
+        let length = local_direction.length();
+        let pdf = object.pdf_value(self.inverse.transform_point(origin), local_direction);
+
+        pdf * self.inverse.linear_determinant().abs() / (length * length * length)
+    }
+
+    fn bounding_box(&self, object: &dyn Hittable) -> Option<Aabb> {
+        object
+            .bounding_box()
+            .map(|bbox| bbox.transformed(&self.matrix))
+    }
+
+    fn contains(&self, object: &dyn Hittable, point: Point) -> bool {
+        object.contains(self.inverse.transform_point(point))
+    }
+}
+
+impl Material for Transform {
+    fn scatter(&self, rec: &HitRecord, ray_in: &Ray) -> Option<Ray> {
+        self.object.scatter(rec, ray_in)
+    }
+
+    fn attenuation(&self, rec: &HitRecord) -> Color {
+        self.object.attenuation(rec)
+    }
+
+    fn reflectance(&self, rec: &HitRecord, ray_in: &Ray, direction: Vec3) -> Color {
+        self.object.reflectance(rec, ray_in, direction)
+    }
+
+    fn emitted(&self, rec: &HitRecord, ray_in: &Ray) -> Color {
+        self.object.emitted(rec, ray_in)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.object.is_emissive()
+    }
+
+    fn is_diffuse(&self) -> bool {
+        self.object.is_diffuse()
+    }
+
+    fn ray_epsilon(&self) -> f64 {
+        self.object.ray_epsilon()
+    }
+}
+
+impl Hittable for Transform {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        self.placement
+            .hit(self.object.as_ref(), ray, t_min, t_max, rec)
+    }
+
+    fn can_be_sampled(&self) -> bool {
+        self.object.can_be_sampled()
+    }
+
+    fn random_point(&self, origin: Point) -> Point {
+        self.placement.random_point(self.object.as_ref(), origin)
+    }
+
+    fn pdf_value(&self, origin: Point, direction: Vec3) -> f64 {
+        self.placement
+            .pdf_value(self.object.as_ref(), origin, direction)
+    }
+
+    fn is_degenerate(&self) -> bool {
+        self.object.is_degenerate()
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.placement.bounding_box(self.object.as_ref())
+    }
+
+    fn contains(&self, point: Point) -> bool {
+        self.placement.contains(self.object.as_ref(), point)
+    }
+}
+
+impl TraceableObjects for Transform {}
+
+/// Many copies (instances) of one object placed into the scene by their own transformations (as by
+/// `Transform`), e.g. a forest of identical trees. The geometry is stored only once, rays are
+/// transformed into the object space of every instance.
+///
+/// Instances whose bounding boxes the ray misses are skipped without transforming the ray. All of
+/// them share the material of the object.
+pub struct InstanceList {
+    object: Arc<dyn TraceableObjects>,
+    instances: Vec<Placement>,
+    /// Bounding boxes of the instances in the world space, `None` if the object is not bounded.
+    boxes: Vec<Option<Aabb>>,
+}
+
+impl InstanceList {
+    /// Every matrix places one instance of the `object`. Returns `None` if any of the matrices
+    /// cannot be inverted.
+    pub fn new(object: Arc<dyn TraceableObjects>, matrices: Vec<Mat4>) -> Option<InstanceList> {
+        let instances = matrices
+            .into_iter()
+            .map(Placement::new)
+            .collect::<Option<Vec<_>>>()?;
+        let boxes = instances
+            .iter()
+            .map(|instance| instance.bounding_box(object.as_ref()))
+            .collect();
+        Some(InstanceList {
+            object,
+            instances,
+            boxes,
+        })
+    }
+
+    /// Number of instances.
+    pub fn len(&self) -> usize {
+        self.instances.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instances.is_empty()
+    }
+}
+
+impl Material for InstanceList {
+    fn scatter(&self, rec: &HitRecord, ray_in: &Ray) -> Option<Ray> {
+        self.object.scatter(rec, ray_in)
+    }
+
+    fn attenuation(&self, rec: &HitRecord) -> Color {
+        self.object.attenuation(rec)
+    }
+
+    fn reflectance(&self, rec: &HitRecord, ray_in: &Ray, direction: Vec3) -> Color {
+        self.object.reflectance(rec, ray_in, direction)
+    }
+
+    fn emitted(&self, rec: &HitRecord, ray_in: &Ray) -> Color {
+        self.object.emitted(rec, ray_in)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.object.is_emissive()
+    }
+
+    fn is_diffuse(&self) -> bool {
+        self.object.is_diffuse()
+    }
+
+    fn ray_epsilon(&self) -> f64 {
+        self.object.ray_epsilon()
+    }
+}
+
+impl Hittable for InstanceList {
+    /// Finds the nearest hit of all the instances.
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let mut candidate = HitRecord::new();
+        let mut closest_t = t_max;
+        let mut hit = false;
+        for (instance, bbox) in self.instances.iter().zip(&self.boxes) {
+            if bbox.is_some_and(|bbox| !bbox.hit(ray, t_min, closest_t)) {
+                continue;
+            }
+            // The record may hold a previous hit (swapped out of `rec`), e.g. its tangents must
+            // not be left for an object which does not set them
+            candidate.reset();
+            if instance.hit(self.object.as_ref(), ray, t_min, closest_t, &mut candidate) {
+                closest_t = candidate.t;
+                hit = true;
+                core::mem::swap(rec, &mut candidate);
+            }
+        }
+        hit
+    }
+
+    fn can_be_sampled(&self) -> bool {
+        self.object.can_be_sampled()
+    }
+
+    /// Picks one of the instances uniformly and a point on it.
+    fn random_point(&self, origin: Point) -> Point {
+        let index = (random::random_range(0., 1.) * self.instances.len() as f64) as usize;
+        self.instances[index.min(self.instances.len() - 1)]
+            .random_point(self.object.as_ref(), origin)
+    }
+
+    /// Average of the densities of the instances, as every one of them is picked by
+    /// `random_point` with the same probability.
+    fn pdf_value(&self, origin: Point, direction: Vec3) -> f64 {
+        let sum: f64 = self
+            .instances
+            .iter()
+            .map(|instance| instance.pdf_value(self.object.as_ref(), origin, direction))
+            .sum();
+        sum / self.instances.len() as f64
+    }
+
+    fn is_degenerate(&self) -> bool {
+        self.instances.is_empty() || self.object.is_degenerate()
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let mut boxes = self.boxes.iter().copied();
+        let first = boxes.next()??;
+        boxes.try_fold(first, |bbox, other| Some(bbox.surrounding(&other?)))
+    }
+
+    /// The point is inside if it is inside of any of the instances.
+    fn contains(&self, point: Point) -> bool {
+        self.instances
+            .iter()
+            .zip(&self.boxes)
+            .filter(|(_, bbox)| bbox.is_none_or(|bbox| bbox.contains(point)))
+            .any(|(instance, _)| instance.contains(self.object.as_ref(), point))
+    }
+}
+
+impl TraceableObjects for InstanceList {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    fn gray() -> Box<dyn Material> {
+        Box::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))
+    }
+
+    #[test]
+    fn sphere_uv_of_poles_and_equator() {
+        let sphere = Sphere::at(1., 2., 3., 2., gray());
+        let uv = |x, y, z| sphere.uv(Point::new(1. + 2. * x, 2. + 2. * y, 3. + 2. * z));
+        let expected = [
+            (uv(1., 0., 0.), (0.5, 0.5)),
+            (uv(-1., 0., 0.), (0., 0.5)),
+            (uv(0., 0., 1.), (0.25, 0.5)),
+            (uv(0., 0., -1.), (0.75, 0.5)),
+            (uv(0., 1., 0.), (0.5, 1.)),
+            (uv(0., -1., 0.), (0.5, 0.)),
+        ];
+        for ((u, v), (expected_u, expected_v)) in expected {
+            assert!((u - expected_u).abs() < 1e-12, "u {} vs {}", u, expected_u);
+            assert!((v - expected_v).abs() < 1e-12, "v {} vs {}", v, expected_v);
+        }
+    }
+
+    #[test]
+    fn sphere_uv_of_too_long_normals_at_poles() {
+        assert_eq!(sphere_uv(Vec3::new(0., -1. - 1e-12, 0.)).1, 0.);
+        assert_eq!(sphere_uv(Vec3::new(0., 1. + 1e-12, 0.)).1, 1.);
+    }
+
+    #[test]
+    fn spheres_without_surface_are_degenerate() {
+        assert!(Sphere::at(0., 0., 0., 0., gray()).is_degenerate());
+        assert!(Sphere::at(0., 0., 0., -1., gray()).is_degenerate());
+        assert!(Sphere::at(0., 0., 0., f64::NAN, gray()).is_degenerate());
+        assert!(Sphere::at(0., 0., 0., f64::INFINITY, gray()).is_degenerate());
+        assert!(Sphere::at(f64::NAN, 0., 0., 1., gray()).is_degenerate());
+        assert!(!Sphere::at(0., 0., 0., 1e-6, gray()).is_degenerate());
+    }
+
+    #[test]
+    fn capsules_without_surface_are_degenerate() {
+        let start = Point::new(0., 0., 0.);
+        let end = Point::new(0., 1., 0.);
+        assert!(Capsule::new(start, end, 0., gray()).is_degenerate());
+        assert!(Capsule::new(start, Point::new(f64::NAN, 0., 0.), 1., gray()).is_degenerate());
+        // Zero length is a sphere, which still has a surface
+        assert!(!Capsule::new(start, start, 1., gray()).is_degenerate());
+    }
+
+    #[test]
+    fn random_points_lie_on_the_capsule() {
+        crate::random::seed(1, 0);
+        let capsule = Capsule::new(Point::new(0., 0., 0.), Point::new(2., 1., 0.), 0.5, gray());
+        let origin = Point::new(0., 5., 0.);
+        for _ in 0..1000 {
+            let point = capsule.random_point(origin);
+            let distance = (point - capsule.nearest_on_axis(point)).length();
+            assert!(
+                (distance - 0.5).abs() < 1e-9,
+                "{} is not on the surface",
+                point
+            );
+        }
+        let towards = (Point::new(1., 0.5, 0.) - origin).unit();
+        assert!(capsule.pdf_value(origin, towards) > 0.);
+    }
+
+    #[test]
+    fn placed_objects_inherit_degeneracy() {
+        let placed = Transform::new(Box::new(Sphere::unit(gray())), Mat4::identity());
+        assert!(!placed.unwrap().is_degenerate());
+        let degenerate = Transform::new(
+            Box::new(Sphere::at(0., 0., 0., 0., gray())),
+            Mat4::identity(),
+        );
+        assert!(degenerate.unwrap().is_degenerate());
+
+        let sphere: Arc<dyn TraceableObjects> = Arc::new(Sphere::unit(gray()));
+        assert!(InstanceList::new(sphere.clone(), vec![])
+            .unwrap()
+            .is_degenerate());
+        assert!(!InstanceList::new(sphere, vec![Mat4::identity()])
+            .unwrap()
+            .is_degenerate());
+    }
+
+    /// Plane `z = 0` facing the +z axis, its hit records have no tangents.
+    struct Wall;
+
+    impl Hittable for Wall {
+        fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+            let t = -ray.origin().z() / ray.direction().z();
+            if !(t_min..t_max).contains(&t) {
+                return false;
+            }
+            rec.t = t;
+            rec.point = ray.at(t);
+            rec.normal = Vec3::new(0., 0., 1.);
+            true
+        }
+    }
+
+    impl Material for Wall {
+        fn scatter(&self, _rec: &HitRecord, _ray_in: &Ray) -> Option<Ray> {
+            None
+        }
+
+        fn attenuation(&self, _rec: &HitRecord) -> Color {
+            Color::black()
+        }
+    }
+
+    impl TraceableObjects for Wall {}
+
+    #[test]
+    fn nearer_instance_does_not_keep_data_of_previous_hit() {
+        let walls = InstanceList::new(
+            Arc::new(Wall),
+            vec![
+                Mat4::translation(Vec3::new(0., 0., -2.)),
+                Mat4::translation(Vec3::new(0., 0., -1.)),
+            ],
+        )
+        .unwrap();
+        let ray = Ray::new(Point::zero(), Vec3::new(0., 0., -1.));
+        let mut rec = HitRecord::new();
+        rec.tangent = Some(Vec3::new(1., 0., 0.));
+        assert!(walls.hit(&ray, 0.001, f64::MAX, &mut rec));
+        assert!((rec.t - 1.).abs() < 1e-12);
+        assert!(rec.tangent.is_none());
+    }
+
+    #[test]
+    fn ray_tangent_to_sphere_does_not_hit_it() {
+        let sphere = Sphere::unit(gray());
+        // The discriminant is exactly zero, the ray touches the sphere at (0, 1, 0)
+        let ray = Ray::new(Point::new(-5., 1., 0.), Vec3::new(1., 0., 0.));
+        assert!(!sphere.hit(&ray, 0.001, f64::MAX, &mut HitRecord::new()));
+
+        // A ray slightly below passes through the sphere near the top
+        let ray = Ray::new(Point::new(-5., 0.999, 0.), Vec3::new(1., 0., 0.));
+        let mut rec = HitRecord::new();
+        assert!(sphere.hit(&ray, 0.001, f64::MAX, &mut rec));
+        assert!(rec.point.x() < 0. && rec.normal.is_finite());
+        assert!((rec.normal.length() - 1.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn degenerate_sphere_is_never_hit() {
+        let sphere = Sphere::at(0., 0., -1., 0., gray());
+        let ray = Ray::new(Point::zero(), Vec3::new(0., 0., -1.));
+        assert!(!sphere.hit(&ray, 0.001, f64::MAX, &mut HitRecord::new()));
+    }
+}
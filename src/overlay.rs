@@ -0,0 +1,78 @@
+use image::{ImageBuffer, Rgba};
+
+use crate::color::Color;
+use crate::math;
+
+/// Rendered image the overlays are drawn onto.
+type Image = ImageBuffer<Rgba<u8>, Vec<u8>>;
+
+/// Blends `color` into the pixel at `(x, y)` with opacity `alpha` (from 0 to 1). The color is
+/// taken as it is displayed (components from 0 to 1 are not gamma-corrected), pixels outside of
+/// the image are ignored, so that overlays can be clipped by its edges.
+pub fn blend_pixel(image: &mut Image, x: i64, y: i64, color: &Color, alpha: f64) {
+    if x < 0 || y < 0 || x >= image.width() as i64 || y >= image.height() as i64 {
+        return;
+    }
+    let alpha = alpha.clamp(0., 1.);
+    let pixel = image.get_pixel_mut(x as u32, y as u32);
+    let mix = |old: u8, new: f64| {
+        math::round(old as f64 * (1. - alpha) + 255. * new.clamp(0., 1.) * alpha) as u8
+    };
+    let [r, g, b, a] = pixel.0;
+    *pixel = Rgba([
+        mix(r, color.r()),
+        mix(g, color.g()),
+        mix(b, color.b()),
+        mix(a, 1.),
+    ]);
+}
+
+/// Draws a line one pixel wide from `start` to `end` (both included) by Bresenham's algorithm.
+pub fn draw_line(image: &mut Image, start: (i64, i64), end: (i64, i64), color: &Color, alpha: f64) {
+    let (mut x, mut y) = start;
+    let dx = (end.0 - x).abs();
+    let dy = -(end.1 - y).abs();
+    let step_x = if x < end.0 { 1 } else { -1 };
+    let step_y = if y < end.1 { 1 } else { -1 };
+    let mut error = dx + dy;
+    loop {
+        blend_pixel(image, x, y, color, alpha);
+        if (x, y) == end {
+            break;
+        }
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += step_y;
+        }
+    }
+}
+
+/// Draws the outline (one pixel wide) of the rectangle with the top left corner at `(x, y)`.
+/// Every pixel is blended once, also in the corners.
+pub fn draw_rect(
+    image: &mut Image,
+    (x, y): (i64, i64),
+    (width, height): (u32, u32),
+    color: &Color,
+    alpha: f64,
+) {
+    if width == 0 || height == 0 {
+        return;
+    }
+    let (right, bottom) = (x + width as i64 - 1, y + height as i64 - 1);
+    draw_line(image, (x, y), (right, y), color, alpha);
+    if bottom > y {
+        draw_line(image, (x, bottom), (right, bottom), color, alpha);
+    }
+    if bottom - y > 1 {
+        draw_line(image, (x, y + 1), (x, bottom - 1), color, alpha);
+        if right > x {
+            draw_line(image, (right, y + 1), (right, bottom - 1), color, alpha);
+        }
+    }
+}
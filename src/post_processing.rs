@@ -0,0 +1,90 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::color::{Color, ColorBuffer};
+use crate::math;
+
+/// Glow around bright areas of the image (e.g. lights), which simulates light scattered in the lens
+/// of a camera. Brightness above the threshold is blurred and added back to the image. Applied to
+/// linear colors, before they are encoded (gamma correction, clipping of highlights).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bloom {
+    threshold: f64,
+    radius: f64,
+    intensity: f64,
+}
+
+impl Bloom {
+    /// * `threshold` - only the part of the color components above it glows (1 is white),
+    /// * `radius` - standard deviation of the blur in pixels,
+    /// * `intensity` - multiplier of the glow added to the image.
+    pub const fn new(threshold: f64, radius: f64, intensity: f64) -> Bloom {
+        Bloom {
+            threshold,
+            radius,
+            intensity,
+        }
+    }
+
+    pub fn apply(&self, buffer: &mut ColorBuffer) {
+        let (width, height) = (buffer.width(), buffer.height());
+        let above = |c: f64| (c - self.threshold).max(0.);
+        let mut glow = ColorBuffer::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let c = buffer.get(x, y);
+                glow.put(x, y, Color::new(above(c.r()), above(c.g()), above(c.b())));
+            }
+        }
+
+        // Gaussian blur is separable, it is applied to the rows and then to the columns
+        let kernel = gaussian_kernel(self.radius);
+        let glow = blur(&blur(&glow, &kernel, (1, 0)), &kernel, (0, 1));
+
+        for y in 0..height {
+            for x in 0..width {
+                buffer[(x, y)] = buffer.get(x, y).copy() + self.intensity * glow.get(x, y).copy();
+            }
+        }
+    }
+}
+
+/// Returns weights of the Gaussian blur with standard deviation `sigma` from the center to the edge
+/// of the kernel (3 sigma), the whole (symmetric) kernel sums to 1.
+fn gaussian_kernel(sigma: f64) -> Vec<f64> {
+    if sigma <= 0. {
+        return vec![1.];
+    }
+    let half_width = math::ceil(3. * sigma) as usize;
+    let mut kernel: Vec<f64> = (0..=half_width)
+        .map(|i| math::exp(-((i * i) as f64) / (2. * sigma * sigma)))
+        .collect();
+    let sum: f64 = kernel[0] + 2. * kernel[1..].iter().sum::<f64>();
+    kernel.iter_mut().for_each(|weight| *weight /= sum);
+    kernel
+}
+
+/// Blurs the buffer in the `direction` (one pixel step, either horizontal or vertical). Pixels out
+/// of the image are replaced by the nearest pixel on the edge.
+fn blur(buffer: &ColorBuffer, kernel: &[f64], direction: (i64, i64)) -> ColorBuffer {
+    let (width, height) = (buffer.width(), buffer.height());
+    let mut result = ColorBuffer::new(width, height);
+    let pixel = |x: i64, y: i64| {
+        buffer.get(
+            x.clamp(0, width as i64 - 1) as u32,
+            y.clamp(0, height as i64 - 1) as u32,
+        )
+    };
+
+    for y in 0..height as i64 {
+        for x in 0..width as i64 {
+            let mut sum = kernel[0] * pixel(x, y).copy();
+            for (i, weight) in kernel.iter().enumerate().skip(1) {
+                let (dx, dy) = (direction.0 * i as i64, direction.1 * i as i64);
+                sum = sum + *weight * (pixel(x + dx, y + dy).copy() + pixel(x - dx, y - dy).copy());
+            }
+            result.put(x as u32, y as u32, sum);
+        }
+    }
+    result
+}
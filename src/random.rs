@@ -0,0 +1,80 @@
+#[cfg(feature = "std")]
+use rand::rngs::StdRng;
+#[cfg(feature = "std")]
+use rand::{Rng, SeedableRng};
+#[cfg(feature = "std")]
+use std::cell::RefCell;
+
+#[cfg(not(feature = "std"))]
+use core::sync::atomic::{AtomicPtr, AtomicU64, Ordering};
+
+#[cfg(feature = "std")]
+thread_local! {
+    /// Every thread has its own generator, so that the threads do not wait for each other and the
+    /// generated sequence does not depend on the order in which the threads ask for numbers.
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
+/// Seeds the generator of the current thread. Numbers generated by the thread afterwards depend
+/// only on `seed` and `stream` (e.g. index of the rendered line), regardless of thread scheduling.
+#[cfg(feature = "std")]
+pub(crate) fn seed(seed: u64, stream: u64) {
+    // Mixes the stream index into the seed (multiplication by the golden ratio spreads the bits),
+    // `seed_from_u64` then expands the value into the whole generator state.
+    let mixed = seed ^ stream.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(mixed));
+}
+
+/// Returns random number in range from `min` (included) to `max` (excluded).
+#[cfg(feature = "std")]
+pub(crate) fn random_range(min: f64, max: f64) -> f64 {
+    RNG.with(|rng| rng.borrow_mut().gen_range(min..max))
+}
+
+/// Generator of random numbers uniformly distributed from 0 (included) to 1 (excluded), e.g.
+/// backed by a hardware generator of the target.
+#[cfg(not(feature = "std"))]
+pub type RandomSource = fn() -> f64;
+
+/// Generator given to `set_random_source`, null until it is called.
+#[cfg(not(feature = "std"))]
+static SOURCE: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+/// State of the built-in generator, which is used until a source is set.
+#[cfg(not(feature = "std"))]
+static STATE: AtomicU64 = AtomicU64::new(0);
+
+/// Without `std`, there are no thread-local generators and all random numbers of the crate (e.g.
+/// scattered directions of materials) come from `source`. Until it is set, a simple built-in
+/// generator (SplitMix64) with a fixed seed is used.
+#[cfg(not(feature = "std"))]
+pub fn set_random_source(source: RandomSource) {
+    SOURCE.store(source as *mut (), Ordering::Release);
+}
+
+/// Returns random number in range from `min` (included) to `max` (excluded).
+#[cfg(not(feature = "std"))]
+pub(crate) fn random_range(min: f64, max: f64) -> f64 {
+    let source = SOURCE.load(Ordering::Acquire);
+    let unit = if source.is_null() {
+        split_mix()
+    } else {
+        // SAFETY: the only non-null value ever stored is a `RandomSource` cast to a pointer
+        let source: RandomSource = unsafe { core::mem::transmute::<*mut (), RandomSource>(source) };
+        source()
+    };
+    min + (max - min) * unit
+}
+
+/// Next number of the built-in generator, from 0 (included) to 1 (excluded).
+#[cfg(not(feature = "std"))]
+fn split_mix() -> f64 {
+    let mut z = STATE
+        .fetch_add(0x9E37_79B9_7F4A_7C15, Ordering::Relaxed)
+        .wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^= z >> 31;
+    // The highest 53 bits fill the mantissa
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}
@@ -7,11 +7,22 @@ use crate::vec3::Vec3 as Point;
 pub struct Ray {
     origin: Vec3,
     direction: Vec3,
+    /// Moment at which the ray exists (while the camera shutter is open), used for motion blur.
+    time: f64,
 }
 
 impl Ray {
+    /// Creates a ray at time 0.
     pub fn new(origin: Point, direction: Vec3) -> Ray {
-        Ray { origin, direction }
+        Ray::new_at_time(origin, direction, 0.)
+    }
+
+    pub fn new_at_time(origin: Point, direction: Vec3, time: f64) -> Ray {
+        Ray {
+            origin,
+            direction,
+            time,
+        }
     }
 
     /// Get value of point `P(t) = A + direction * t`
@@ -20,7 +31,7 @@ impl Ray {
     }
 
     pub fn unit_vector(&self) -> Vec3 {
-        self.direction / self.direction.length()
+        self.direction.unit()
     }
 
     pub fn direction(&self) -> Vec3 {
@@ -30,4 +41,14 @@ impl Ray {
     pub fn origin(&self) -> Point {
         self.origin
     }
+
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    /// Returns `false` if the origin or the direction has a NaN or infinite component. Comparisons
+    /// with NaN are always false, therefore such a ray could hit nothing or anything.
+    pub fn is_finite(&self) -> bool {
+        self.origin.is_finite() && self.direction.is_finite()
+    }
 }
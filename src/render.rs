@@ -0,0 +1,2253 @@
+//! Rendering of scenes into images by multiple threads, the renderer needs the `std` feature.
+
+use image::codecs::hdr::HdrEncoder;
+use image::{ImageBuffer, Rgb, Rgba};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::hash_map::DefaultHasher;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::BufWriter;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+use crate::camera::{Camera, PerspectiveCamera};
+use crate::checkpoint::Checkpoint;
+use crate::color::{Color, ColorBuffer, ColorPipeline, Encoding, Highlights};
+use crate::environment::EnvironmentMap;
+use crate::hit_record::HitRecord;
+use crate::material::{Lambertian, Material, Metal};
+use crate::objects::Sphere;
+use crate::post_processing::Bloom;
+use crate::ray::Ray;
+use crate::scene::Scene;
+use crate::stats::RayStats;
+use crate::texture::ImageTexture;
+use crate::thread_pool::ThreadPool;
+use crate::vec3::Vec3;
+use crate::vec3::Vec3 as Point; // For better understanding of the code
+use crate::{material, math, random, TraceableObjects};
+
+const INFINITY: f64 = f64::MAX;
+/// Camera rays ignore objects closer than `NEAR_CLIP` and farther than `FAR_CLIP` (distances from
+/// the camera). Camera rays do not start on a surface, any small near distance works.
+const NEAR_CLIP: f64 = 0.001;
+const FAR_CLIP: f64 = INFINITY;
+
+/// Supersampling anti-aliasing parameter
+const SAMPLES_PER_PIXEL: u16 = 16;
+/// Upper limit for ray reflections
+const MAX_DEPTH: u16 = 10;
+/// `Integrator::AmbientOcclusion { samples: 16, radius: 0.5 }` renders a quick preview of the
+/// shape of the scene, `Integrator::Normals` shows normals of the surfaces and
+/// `Integrator::Albedo` their base colors (the normals and albedo are guides for denoisers),
+/// `Integrator::BounceTint` shows where light bounces many times
+const INTEGRATOR: Integrator = Integrator::PathTracing;
+/// 0 or 1 renders the image in the calling thread (e.g. for debugging or on platforms without
+/// threads)
+const THREAD_COUNT: u8 = 8;
+const OUTPUT_FILE_NAME: &str = "image.png";
+/// Row 0 of the image is the bottom of the picture (OpenGL textures) instead of the top (PNG)
+const FLIP_Y: bool = false;
+/// Pixels where no object was hit are transparent (for compositing with other images)
+const TRANSPARENT_BACKGROUND: bool = false;
+/// Encoding of the rendered colors, `Encoding::Srgb` is the exact transfer function expected by
+/// displays (`Encoding::Gamma2` approximates it), `Encoding::Linear` skips gamma correction (for
+/// tone mapping the image in another tool)
+const COLOR_ENCODING: Encoding = Encoding::Gamma2;
+/// Weights of the samples of a pixel by their distance from its center, e.g.
+/// `PixelFilter::Gaussian { sigma: 0.3 }` gives sharper edges with less aliasing than
+/// `PixelFilter::Box`, which weights all samples equally
+const PIXEL_FILTER: PixelFilter = PixelFilter::Box;
+/// Image is rendered at this multiple of its resolution and then scaled down by averaging blocks
+/// of pixels (supersampling anti-aliasing), 1 disables it
+const SUPERSAMPLING: u32 = 1;
+/// `Some(Bloom::new(1.0, 8.0, 0.5))` adds glow around lights and other very bright areas
+const BLOOM: Option<Bloom> = None;
+/// `Highlights::Knee(0.8)` compresses the brightest values smoothly instead of clipping them
+const HIGHLIGHTS: Highlights = Highlights::Clip;
+/// One sample through the center of every pixel instead of `SAMPLES_PER_PIXEL` jittered ones.
+/// Random numbers of the paths are seeded (by `RENDER_SEED`, or 0), so the image is exactly the
+/// same every time, e.g. for comparing renders before and after a change of the code
+const CENTER_SAMPLE: bool = false;
+/// The image is rendered in passes of 1, 1, 2, 4, ... samples per pixel (up to
+/// `SAMPLES_PER_PIXEL` in total) and saved after every pass, so that a noisy preview is available
+/// soon and gets refined over time
+const PROGRESSIVE: bool = false;
+/// `Some(256)` renders the image in horizontal strips of 256 lines, every strip is saved into its
+/// own file (`image.0000.png`, `image.0001.png`, ...) and only one of them is kept in memory, so
+/// that very large images can be rendered with little memory
+const STRIP_HEIGHT: Option<u32> = None;
+/// Equirectangular HDR image (`.hdr`) surrounding the scene, e.g. `Some("sky.hdr")`. It replaces
+/// the default sky and lights diffuse surfaces through shadow rays aimed at its bright parts
+const ENVIRONMENT_MAP: Option<&str> = None;
+/// `Some(10.)` limits the light brought by a single scattered ray to 10 (in every component), which
+/// removes most fireflies (isolated bright pixels from rarely found light paths) at the cost of
+/// slightly darker indirect lighting
+const INDIRECT_CLAMP: Option<f64> = None;
+/// PNG images get chunks telling viewers how the colors are encoded (`COLOR_ENCODING`): sRGB, or
+/// the gamma of `Encoding::Gamma2` and `Encoding::Linear`, which look wrong if viewers assume sRGB
+const COLOR_SPACE_METADATA: bool = true;
+/// `Some("image.hdr")` saves linear colors of the render into a Radiance HDR file as well, e.g. for
+/// tone mapping in another tool. Both files are made from the same samples, the image is rendered
+/// only once. Checkpoints and strips (`STRIP_HEIGHT`) are not used with it
+const HDR_OUTPUT_FILE_NAME: Option<&str> = None;
+
+/// Seed of the random numbers used for rendering, the same seed gives exactly the same image.
+/// `None` means a different image (noise) every time.
+const RENDER_SEED: Option<u64> = None;
+/// Index of the first sample of every pixel. With `RENDER_SEED`, renders of different ranges of
+/// samples (e.g. starting at 0 and at `SAMPLES_PER_PIXEL`) use different random numbers, so that
+/// the same image can be rendered on several machines and the results averaged.
+const FIRST_SAMPLE: u32 = 0;
+/// Renders the random scene generated from this seed instead of the one from `set_scene_objects`
+/// (unless the scene is selected on the command line, see `SceneChoice::from_args`)
+const RANDOM_SCENE_SEED: Option<u64> = None;
+/// Number of small spheres of the random scene, 484 (22×22 grid) is the scene from the book
+const RANDOM_SCENE_COUNT: usize = 484;
+
+const IMAGE_WIDTH: u32 = 1920;
+const IMAGE_ASPECT_RATIO: f64 = 16.0 / 9.0;
+/// Ratio of the width of a pixel to its height, other than 1.0 for anamorphic formats
+const PIXEL_ASPECT_RATIO: f64 = 1.0;
+const CAM_FOCAL_LENGTH: f64 = 1.0;
+const CAM_HEIGHT: f64 = 2.0;
+
+/// Holds information about dimensions of the resulting image.
+#[derive(Clone, Copy, Debug)]
+pub struct Image {
+    width: u32,
+    height: u32,
+    /// Ratio of the width of a pixel to its height (pixels are not square on some devices).
+    pixel_aspect_ratio: f64,
+}
+
+impl Image {
+    /// `aspect_ratio` is the ratio of the image as it is displayed, i.e. with non-square pixels
+    /// stretched by `pixel_aspect_ratio`.
+    pub fn new(width: u32, aspect_ratio: f64, pixel_aspect_ratio: f64) -> Image {
+        Image {
+            width,
+            height: (width as f64 * pixel_aspect_ratio / aspect_ratio) as u32,
+            pixel_aspect_ratio,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Real aspect ratio of the displayed image, it can slightly differ from the one given to `new`
+    /// because the height is rounded to whole pixels.
+    pub fn aspect_ratio(&self) -> f64 {
+        self.width as f64 * self.pixel_aspect_ratio / self.height as f64
+    }
+
+    /// Returns `true` if the image with the given `aspect_ratio` would differ from this one by
+    /// at most one pixel of height (that is the precision of the image dimensions).
+    fn matches_aspect_ratio(&self, aspect_ratio: f64) -> bool {
+        let expected_height = self.width as f64 * self.pixel_aspect_ratio / aspect_ratio;
+        (expected_height - self.height as f64).abs() <= 1.0
+    }
+}
+
+/// Algorithm computing the colors of camera rays.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Integrator {
+    /// Physically based light transport, see `calculate_color`.
+    PathTracing,
+    /// Quick preview of the shape of the geometry, which ignores materials and lights. Surfaces
+    /// are shaded by the fraction of `samples` rays from the hit point that do not hit any object
+    /// within `radius`.
+    AmbientOcclusion { samples: u16, radius: f64 },
+    /// Surfaces are colored by their normals mapped to RGB (`0.5 * (normal + 1)`), for checking the
+    /// normals of the objects. Materials and lights are ignored, the values are finished by
+    /// `RenderConfig::aov_pipeline`.
+    Normals,
+    /// Surfaces are colored by the base color of their materials (albedo) without any lighting,
+    /// which gives a noise-free guide image for denoisers. Lights show their emitted color limited
+    /// to 1 and the background its own color. The values are finished by
+    /// `RenderConfig::aov_pipeline`.
+    Albedo,
+    /// Path tracing where the color of every surface is replaced by a tint of the bounce (light
+    /// reflected once is tinted red, twice green, three times blue, then the tints repeat), which
+    /// shows how much the individual bounces contribute and why `MAX_DEPTH` changes the image.
+    /// Lights are found by the scattered rays only (no shadow rays).
+    BounceTint,
+}
+
+/// Reconstruction filter, which weights the samples of a pixel by their offset from its center.
+/// Samples are taken uniformly within the `radius` of the filter, which may reach into the
+/// neighbouring pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PixelFilter {
+    /// All samples have the same weight.
+    Box,
+    /// The weight falls off linearly with the horizontal and vertical distance from the center
+    /// and reaches zero at `radius` (in pixels). Radii below 0.5 are treated as 0.5, so that the
+    /// whole pixel is covered.
+    Tent { radius: f64 },
+    /// Gaussian falloff with the standard deviation `sigma` (in pixels), cut off at `3 sigma`.
+    /// Values below 0.1 are treated as 0.1.
+    Gaussian { sigma: f64 },
+}
+
+impl PixelFilter {
+    /// Largest horizontal and vertical offset of a sample from the center of the pixel (in
+    /// pixels), the filter is zero (or negligible) beyond it.
+    pub fn radius(self) -> f64 {
+        match self {
+            PixelFilter::Box => 0.5,
+            PixelFilter::Tent { radius } => radius.max(0.5),
+            PixelFilter::Gaussian { sigma } => 3. * sigma.max(0.1),
+        }
+    }
+
+    /// Weight of the sample at offset `(dx, dy)` from the center of the pixel (in pixels).
+    pub fn weight(self, dx: f64, dy: f64) -> f64 {
+        match self {
+            PixelFilter::Box => 1.,
+            PixelFilter::Tent { radius } => {
+                let radius = radius.max(0.5);
+                (1. - dx.abs() / radius).max(0.) * (1. - dy.abs() / radius).max(0.)
+            }
+            PixelFilter::Gaussian { sigma } => {
+                let sigma = sigma.max(0.1);
+                math::exp(-(dx * dx + dy * dy) / (2. * sigma * sigma))
+            }
+        }
+    }
+}
+
+/// Function returning the color of rays that do not hit any object in the scene.
+pub type Background = Arc<dyn Fn(&Ray) -> Color + Send + Sync>;
+
+/// State of the render passed to the progress callback after every finished line.
+#[derive(Clone, Copy, Debug)]
+pub struct Progress {
+    /// Finished fraction of the image (from 0 to 1), lines loaded from the checkpoint included.
+    pub fraction: f64,
+    /// Estimated time until the render finishes, based on the speed of the lines rendered so far.
+    pub remaining: Duration,
+}
+
+/// Function receiving the progress of the render, called from the thread that started it.
+pub type ProgressCallback = Arc<dyn Fn(Progress) + Send + Sync>;
+
+/// Finished rectangle of the image passed to the tile callback, e.g. for writing it into a file
+/// format with random access to tiles. The image is rendered by lines, so every tile is one whole
+/// line at the moment.
+#[derive(Clone, Copy, Debug)]
+pub struct Tile<'a> {
+    /// Column of the left edge of the tile in the image.
+    pub x: u32,
+    /// Row of the top edge of the tile in the image (the whole image, also when only some of its
+    /// rows are rendered).
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    /// RGBA pixels of the tile (8 bits per channel) row by row, as they are in the image.
+    pub pixels: &'a [u8],
+}
+
+impl Integrator {
+    /// Returns `true` for integrators producing data for other tools (arbitrary output variables,
+    /// AOVs) rather than the lit image.
+    pub fn is_aov(self) -> bool {
+        matches!(self, Integrator::Normals | Integrator::Albedo)
+    }
+}
+
+/// Function receiving finished tiles of the image, called from the thread that started the render.
+pub type TileCallback = Arc<dyn Fn(Tile<'_>) + Send + Sync>;
+
+/// Rendering settings that are not given by the scene itself. `RenderConfig::new` gives the
+/// defaults of the program, the public fields can be changed before the render, e.g.
+/// `config.samples = 64`.
+#[derive(Clone)]
+pub struct RenderConfig {
+    /// Color of the sky, default is a vertical gradient from white to blue.
+    pub background: Background,
+    /// Image surrounding the scene, it is used instead of `background`. Its light is sampled
+    /// directly from diffuse surfaces as the light of scene lights.
+    pub environment: Option<Arc<EnvironmentMap>>,
+    /// Sidecar file for resuming interrupted renders, `None` (default) disables checkpoints. The
+    /// file is started over if the image, the render settings or the scene do not match it (see
+    /// `checkpoint_key` for what is compared).
+    pub checkpoint_file: Option<PathBuf>,
+    /// Alpha channel of the image is the fraction of camera rays that hit an object (0 where all
+    /// of them escaped to the background), otherwise the whole image is opaque.
+    pub transparent_background: bool,
+    /// Every sample is rendered with random numbers seeded by this seed, the pixel and the index of
+    /// the sample, so the result does not depend on the scheduling of the threads.
+    pub seed: Option<u64>,
+    /// Number of samples of every pixel, at least 1.
+    pub samples: u16,
+    /// Samples of every pixel are numbered from `first_sample`, only matters with `seed`.
+    pub first_sample: u32,
+    /// Every pixel gets a single sample through its center (no anti-aliasing), paths are seeded
+    /// by `seed` or 0 if it is `None`.
+    pub center_sample: bool,
+    /// Weights of the samples within a pixel.
+    pub filter: PixelFilter,
+    /// Objects closer to the camera than `near_clip` or farther than `far_clip` are not visible
+    /// to camera rays (they still cast shadows and appear in reflections).
+    pub near_clip: f64,
+    pub far_clip: f64,
+    /// The first row of the image shows the bottom of the scene, otherwise it shows the top (which
+    /// is the convention of most image formats).
+    pub flip_y: bool,
+    /// Encoding of the color pass told to image viewers (PNG metadata), it is expected to be the
+    /// transfer function of `color_pipeline`.
+    pub color_encoding: Encoding,
+    /// Operations turning the averaged linear colors into the values of the image.
+    pub color_pipeline: Arc<ColorPipeline>,
+    /// Same as `color_encoding` for the passes holding data rather than colors (see
+    /// `Integrator::is_aov`), default is `Encoding::Linear`.
+    pub aov_encoding: Encoding,
+    /// Same as `color_pipeline` for the passes holding data, by default the values are only
+    /// clipped, so that e.g. normals keep their exact values.
+    pub aov_pipeline: Arc<ColorPipeline>,
+    /// Linear colors of the pixels are returned together with the 8-bit image. The checkpoint
+    /// holds only the 8-bit data, therefore it is not used in this case.
+    pub keep_colors: bool,
+    /// The image is rendered at `supersampling` times larger resolution and scaled down, which
+    /// costs `supersampling²` times more memory and time. Checkpoints are not used with it.
+    pub supersampling: u32,
+    /// Glow around bright areas, checkpoints are not used with it (as with `keep_colors`).
+    pub bloom: Option<Bloom>,
+    /// Algorithm computing colors of camera rays.
+    pub integrator: Integrator,
+    /// Maximum component of the light brought by a scattered ray (after the attenuation by the
+    /// surface), brighter colors are scaled down keeping their hue. `None` keeps the unbiased
+    /// result.
+    pub indirect_clamp: Option<f64>,
+    /// Receives the progress of the render, e.g. for a progress bar.
+    pub progress: Option<ProgressCallback>,
+    /// Receives every finished tile of the image (including those loaded from the checkpoint).
+    pub on_tile: Option<TileCallback>,
+    /// Pixels started after this time get only one sample, so that the render finishes soon.
+    pub deadline: Option<Instant>,
+    /// Every sample and every bounce of its path are logged by `log::debug!`, which is meant for
+    /// a single pixel (see `debug_pixel`).
+    trace: bool,
+    /// Only these rows of the image are rendered and returned, `None` renders the whole image.
+    /// Checkpoints are not used with it.
+    pub rows: Option<Range<u32>>,
+}
+
+impl RenderConfig {
+    pub fn new() -> RenderConfig {
+        RenderConfig {
+            background: Arc::new(|ray| linearly_blend_colors(ray, Color::white(), Color::blue())),
+            environment: None,
+            checkpoint_file: None,
+            transparent_background: TRANSPARENT_BACKGROUND,
+            seed: RENDER_SEED,
+            samples: SAMPLES_PER_PIXEL,
+            first_sample: FIRST_SAMPLE,
+            center_sample: CENTER_SAMPLE,
+            filter: PIXEL_FILTER,
+            near_clip: NEAR_CLIP,
+            far_clip: FAR_CLIP,
+            integrator: INTEGRATOR,
+            indirect_clamp: INDIRECT_CLAMP,
+            flip_y: FLIP_Y,
+            color_encoding: COLOR_ENCODING,
+            color_pipeline: Arc::new(color_pipeline()),
+            aov_encoding: Encoding::Linear,
+            aov_pipeline: Arc::new(ColorPipeline::new().then(Encoding::Linear)),
+            keep_colors: false,
+            supersampling: SUPERSAMPLING,
+            bloom: BLOOM,
+            progress: None,
+            on_tile: None,
+            deadline: None,
+            trace: false,
+            rows: None,
+        }
+    }
+
+    /// Encoding of the image rendered by `integrator`, `aov_encoding` or `color_encoding`.
+    pub fn encoding(&self) -> Encoding {
+        if self.integrator.is_aov() {
+            self.aov_encoding
+        } else {
+            self.color_encoding
+        }
+    }
+
+    /// Pipeline finishing the pixels rendered by `integrator`, `aov_pipeline` or `color_pipeline`.
+    pub fn pipeline(&self) -> &ColorPipeline {
+        if self.integrator.is_aov() {
+            &self.aov_pipeline
+        } else {
+            &self.color_pipeline
+        }
+    }
+
+    /// Number of samples every pixel gets (one with `center_sample`).
+    pub fn samples_per_pixel(&self) -> u16 {
+        if self.center_sample {
+            1
+        } else {
+            self.samples
+        }
+    }
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        RenderConfig::new()
+    }
+}
+
+/// Operations applied to the average of the samples of every pixel, e.g.
+/// `ColorPipeline::new().then(Exposure::new(1.)).then(COLOR_ENCODING)` brightens the image by one
+/// stop. Steps can be reordered or custom ones (implementing `ColorOperation`) added.
+fn color_pipeline() -> ColorPipeline {
+    ColorPipeline::standard(COLOR_ENCODING, HIGHLIGHTS)
+}
+
+fn set_scene_objects(scene: &mut Scene) {
+    let diffused = Box::new(Lambertian::new(Color::from_frac(0.8, 0.2, 0.2).unwrap()));
+    let sphere = Sphere::at(0., 0., -1., 0.5, diffused);
+    scene.add(sphere);
+    let metal = Box::new(Metal::fuzzy(Color::from_frac(0.8, 0.8, 0.8).unwrap(), 0.3));
+    let sphere = Sphere::at(-1., 0., -1., 0.5, metal);
+    scene.add(sphere);
+    let metal = Box::new(Metal::shiny(Color::from_frac(0.5, 0.6, 0.6).unwrap()));
+    let sphere = Sphere::at(1., 0., -1., 0.5, metal);
+    scene.add(sphere);
+    let diffused = Box::new(Lambertian::new(Color::from_frac(0.05, 0.5, 0.05).unwrap()));
+    let sphere = Sphere::at(0., -100.5, -1., 100., diffused);
+    scene.add(sphere);
+}
+
+/// Generates the final scene of the "Ray Tracing in One Weekend" book: a lot of small spheres with
+/// random materials around three big ones. The same `seed` and `count` always give the same scene,
+/// so it can be used as a benchmark. Glass spheres from the book are replaced by shiny metal ones.
+///
+/// Small spheres are placed on a square grid around the origin, one per cell, `count` of them at
+/// most (those overlapping the big spheres are left out).
+///
+/// Returns the scene together with the camera looking at it.
+fn random_scene(seed: u64, count: usize, aspect_ratio: f64) -> (Scene, PerspectiveCamera) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut scene = Scene::new();
+
+    let ground = Box::new(Lambertian::new(Color::from_frac(0.5, 0.5, 0.5).unwrap()));
+    let sphere = Sphere::at(0., -1000., 0., 1000., ground);
+    scene.add(sphere);
+
+    let side = math::ceil(math::sqrt(count as f64)) as i64;
+    let grid = -side / 2..side - side / 2;
+    let cells = grid
+        .clone()
+        .flat_map(|a| grid.clone().map(move |b| (a, b)))
+        .take(count);
+    for (a, b) in cells {
+        let choose_material: f64 = rng.gen_range(0.0..1.0);
+        let center = Point::new(
+            a as f64 + 0.9 * rng.gen_range(0.0..1.0),
+            0.2,
+            b as f64 + 0.9 * rng.gen_range(0.0..1.0),
+        );
+        // Do not overlap with the big metal sphere
+        if (center - Point::new(4., 0.2, 0.)).length() <= 0.9 {
+            continue;
+        }
+
+        let material: Box<dyn Material> = if choose_material < 0.8 {
+            let albedo = Color::from_frac(
+                rng.gen_range(0.0..1.0) * rng.gen_range(0.0..1.0),
+                rng.gen_range(0.0..1.0) * rng.gen_range(0.0..1.0),
+                rng.gen_range(0.0..1.0) * rng.gen_range(0.0..1.0),
+            );
+            Box::new(Lambertian::new(albedo.unwrap()))
+        } else if choose_material < 0.95 {
+            let albedo = Color::from_frac(
+                rng.gen_range(0.5..1.0),
+                rng.gen_range(0.5..1.0),
+                rng.gen_range(0.5..1.0),
+            );
+            Box::new(Metal::fuzzy(albedo.unwrap(), rng.gen_range(0.0..0.5)))
+        } else {
+            Box::new(Metal::shiny(Color::white()))
+        };
+        let sphere = Sphere::new(center, 0.2, material);
+        scene.add(sphere);
+    }
+
+    let metal = Box::new(Metal::shiny(Color::white()));
+    let sphere = Sphere::at(0., 1., 0., 1.0, metal);
+    scene.add(sphere);
+    let diffused = Box::new(Lambertian::new(Color::from_frac(0.4, 0.2, 0.1).unwrap()));
+    let sphere = Sphere::at(-4., 1., 0., 1.0, diffused);
+    scene.add(sphere);
+    let metal = Box::new(Metal::shiny(Color::from_frac(0.7, 0.6, 0.5).unwrap()));
+    let sphere = Sphere::at(4., 1., 0., 1.0, metal);
+    scene.add(sphere);
+
+    let camera = PerspectiveCamera::look_at(
+        Point::new(13., 2., 3.),
+        Point::zero(),
+        Vec3::new(0., 1., 0.),
+        20.,
+        aspect_ratio,
+    );
+
+    (scene, camera)
+}
+
+/// Scene to be rendered by `run`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SceneChoice {
+    /// Scene from `set_scene_objects`.
+    Default,
+    /// Scene generated by `random_scene`.
+    Random { count: usize, seed: u64 },
+}
+
+/// Options of `run` given on the command line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options {
+    pub scene: SceneChoice,
+    /// The render stops taking new samples once this time passes and saves what it has.
+    pub time_limit: Option<Duration>,
+    /// Only this pixel (column, row from the top) is rendered and every bounce of its paths is
+    /// logged, nothing is saved.
+    pub debug_pixel: Option<(u32, u32)>,
+    /// Finished lines are stored into this file, so that an interrupted render can be resumed by
+    /// running the program again with the same options.
+    pub checkpoint: Option<PathBuf>,
+}
+
+impl Options {
+    /// Parses command line arguments (without the program name):
+    /// * `--scene default|random` - scene to render,
+    /// * `--count N` - number of small spheres of the random scene,
+    /// * `--seed S` - seed of the random scene,
+    /// * `--time-limit T` - time budget of the render, in seconds (`10` or `10s`) or minutes
+    ///   (`2m`),
+    /// * `--debug-pixel X,Y` - traces the single pixel at column `X` and row `Y` (see
+    ///   `debug_pixel`),
+    /// * `--checkpoint FILE` - stores finished lines into `FILE` and resumes the render from it.
+    ///
+    /// `--count` and `--seed` select the random scene if `--scene` is not given. Missing values are
+    /// taken from `RANDOM_SCENE_SEED` (0 if it is `None`) and `RANDOM_SCENE_COUNT`.
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Options, String> {
+        let mut scene = None;
+        let mut count = None;
+        let mut seed = None;
+        let mut time_limit = None;
+        let mut debug_pixel = None;
+        let mut checkpoint = None;
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("Missing value of {}", arg))?;
+            match arg.as_str() {
+                "--scene" => scene = Some(value),
+                "--count" => {
+                    let parsed = value
+                        .parse()
+                        .map_err(|e| format!("Invalid --count: {}", e))?;
+                    count = Some(parsed);
+                }
+                "--seed" => {
+                    let parsed = value
+                        .parse()
+                        .map_err(|e| format!("Invalid --seed: {}", e))?;
+                    seed = Some(parsed);
+                }
+                "--time-limit" => time_limit = Some(parse_duration(&value)?),
+                "--debug-pixel" => {
+                    let parsed = value
+                        .split_once(',')
+                        .and_then(|(x, y)| Some((x.trim().parse().ok()?, y.trim().parse().ok()?)))
+                        .ok_or_else(|| format!("Invalid --debug-pixel: {}, expected X,Y", value))?;
+                    debug_pixel = Some(parsed);
+                }
+                "--checkpoint" => checkpoint = Some(PathBuf::from(value)),
+                _ => return Err(format!("Unknown argument {}", arg)),
+            }
+        }
+
+        let random_args = count.is_some() || seed.is_some();
+        let random = match scene.as_deref() {
+            None => random_args || RANDOM_SCENE_SEED.is_some(),
+            Some("random") => true,
+            Some("default") if random_args => {
+                return Err("--count and --seed apply only to the random scene".to_string())
+            }
+            Some("default") => false,
+            Some(other) => {
+                return Err(format!(
+                    "Unknown scene {}, expected default or random",
+                    other
+                ))
+            }
+        };
+        let scene = if random {
+            SceneChoice::Random {
+                count: count.unwrap_or(RANDOM_SCENE_COUNT),
+                seed: seed.or(RANDOM_SCENE_SEED).unwrap_or(0),
+            }
+        } else {
+            SceneChoice::Default
+        };
+        Ok(Options {
+            scene,
+            time_limit,
+            debug_pixel,
+            checkpoint,
+        })
+    }
+}
+
+/// Parses a non-negative duration in seconds, optionally with suffix `s`, or in minutes with
+/// suffix `m`.
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let (number, unit) = match value.strip_suffix('m') {
+        Some(minutes) => (minutes, 60.),
+        None => (value.strip_suffix('s').unwrap_or(value), 1.),
+    };
+    number
+        .parse::<f64>()
+        .ok()
+        .and_then(|n| Duration::try_from_secs_f64(n * unit).ok())
+        .ok_or_else(|| format!("Invalid --time-limit: {}", value))
+}
+
+/// Renders the scene and saves it into `OUTPUT_FILE_NAME`.
+///
+/// With `options.time_limit`, the image is rendered progressively (see `render_progressive`) and
+/// the passes that finish within the limit are saved. Pixels of the pass cut by the limit get fewer
+/// samples, they are averaged over the samples they actually got.
+pub fn run(options: Options) {
+    let image = Image::new(IMAGE_WIDTH, IMAGE_ASPECT_RATIO, PIXEL_ASPECT_RATIO);
+
+    // The camera takes the aspect ratio from the image, so that the result is not stretched
+    let (camera_viewport, scene): (Arc<dyn Camera>, _) = match options.scene {
+        SceneChoice::Random { count, seed } => {
+            let (scene, camera) = random_scene(seed, count, image.aspect_ratio());
+            (Arc::new(camera), scene)
+        }
+        SceneChoice::Default => {
+            let mut scene = Scene::new();
+            set_scene_objects(&mut scene);
+            let camera = PerspectiveCamera::new(CAM_HEIGHT, image.aspect_ratio(), CAM_FOCAL_LENGTH);
+            (Arc::new(camera), scene)
+        }
+    };
+    let scene = Arc::new(scene);
+    match scene.bounding_box() {
+        Some(bbox) => log::info!(
+            "Scene of {} objects spans from {} to {}",
+            scene.len(),
+            bbox.min(),
+            bbox.max()
+        ),
+        None => log::info!("Scene of {} objects is not bounded", scene.len()),
+    }
+
+    let mut config = RenderConfig::new();
+    config.checkpoint_file = options.checkpoint;
+    if let Some(path) = ENVIRONMENT_MAP {
+        match ImageTexture::open_hdr(path) {
+            Ok(texture) => match EnvironmentMap::new(texture) {
+                Some(environment) => config.environment = Some(Arc::new(environment)),
+                None => log::warn!("Environment map {} is black, ignoring it", path),
+            },
+            Err(e) => log::warn!("Cannot load environment map {}: {}", path, e),
+        }
+    }
+    config.progress = Some(Arc::new(|progress| {
+        log::info!(
+            "Rendered {:.1} %, {} s remaining",
+            100. * progress.fraction,
+            progress.remaining.as_secs()
+        )
+    }));
+
+    if let Some((x, y)) = options.debug_pixel {
+        if x < image.width && y < image.height {
+            debug_pixel(&camera_viewport, &image, &config, &scene, x, y);
+        } else {
+            log::error!(
+                "Pixel ({}, {}) is out of the {}x{} image",
+                x,
+                y,
+                image.width,
+                image.height
+            );
+        }
+        return;
+    }
+
+    let metadata = COLOR_SPACE_METADATA.then_some(config.encoding());
+    config.keep_colors = HDR_OUTPUT_FILE_NAME.is_some();
+    // The budget starts after the scene is built, it is meant for the rendering itself
+    config.deadline = options.time_limit.map(|limit| Instant::now() + limit);
+
+    if let Some(strip_height) = STRIP_HEIGHT {
+        if HDR_OUTPUT_FILE_NAME.is_some() {
+            log::warn!("The HDR image is not saved when rendering in strips");
+        }
+        if let Err(e) = render_strips(
+            &camera_viewport,
+            &image,
+            &config,
+            &scene,
+            strip_height,
+            OUTPUT_FILE_NAME,
+        ) {
+            log::error!("{}", e);
+        }
+    } else {
+        let colors = if PROGRESSIVE || config.deadline.is_some() {
+            render_progressive(
+                &camera_viewport,
+                &image,
+                &config,
+                &scene,
+                |image_buffer, samples| {
+                    save_image(image_buffer, OUTPUT_FILE_NAME, metadata);
+                    log::info!("Saved the image with {} samples per pixel", samples);
+                },
+            )
+            .map(|(_, colors)| colors)
+        } else {
+            calculate_image(&camera_viewport, &image, &config, &scene).map(
+                |(image_buffer, colors)| {
+                    save_image(&image_buffer, OUTPUT_FILE_NAME, metadata);
+                    colors
+                },
+            )
+        };
+        match colors {
+            Ok(colors) => {
+                if let (Some(filename), Some(colors)) = (HDR_OUTPUT_FILE_NAME, colors) {
+                    if let Err(e) = save_hdr(&colors, filename) {
+                        log::error!("Cannot save the HDR image {}: {}", filename, e);
+                    }
+                }
+            }
+            Err(e) => log::error!("{}", e),
+        }
+    }
+}
+
+/// Renders the image in passes, every pass adds as many samples per pixel as all the previous
+/// ones together (the first one has a single sample), until `config.samples` is reached. The
+/// samples are accumulated as linear colors and `on_pass` gets the image encoded after every pass
+/// together with the number of samples per pixel so far. `config.bloom` is applied to the average
+/// of all the passes so far, before it is encoded.
+///
+/// With `config.seed`, the result is the same as a render with all the samples at once (up to
+/// rounding errors of the accumulation).
+///
+/// No pass is started after `config.deadline`. The pass running at the deadline is finished with
+/// a single sample for the remaining pixels, every pixel is weighted by the number of samples it
+/// actually got.
+fn render_progressive<F>(
+    cam: &Arc<dyn Camera>,
+    image: &Image,
+    config: &RenderConfig,
+    scene: &Arc<Scene>,
+    mut on_pass: F,
+) -> Result<Rendered, RenderError>
+where
+    F: FnMut(&ImageBuffer<Rgba<u8>, Vec<u8>>, u16),
+{
+    let total = config.samples_per_pixel();
+    if total == 0 {
+        return Err(RenderError::NoSamples);
+    }
+    let mut pass_config = config.clone();
+    pass_config.keep_colors = true;
+    pass_config.bloom = None;
+    let mut sum: Option<(ColorBuffer, Vec<f64>)> = None;
+    let mut done = 0;
+    // Both buffers are allocated once and reused by all passes
+    let rows = config.rows.clone().unwrap_or(0..image.height);
+    let mut pass_buffer = ImageBuffer::new(image.width, rows.end.saturating_sub(rows.start));
+    let mut image_buffer = pass_buffer.clone();
+    let mut average = ColorBuffer::new(pass_buffer.width(), pass_buffer.height());
+    while done < total {
+        if done > 0 && config.deadline.is_some_and(|d| Instant::now() >= d) {
+            log::info!(
+                "Time limit reached, stopping with {} of {} samples per pixel",
+                done,
+                total
+            );
+            break;
+        }
+        let samples = done.clamp(1, total - done);
+        pass_config.samples = samples;
+        pass_config.first_sample = config.first_sample + done as u32;
+        let pass_colors =
+            calculate_image_into(cam, image, &pass_config, scene, &mut pass_buffer)?.unwrap();
+
+        // Pass results are averages of their samples, they are weighted by the number of them
+        // (pixels cut by the deadline have fewer samples than the pass)
+        let (colors, alpha) = sum.get_or_insert_with(|| {
+            let (width, height) = pass_buffer.dimensions();
+            (
+                ColorBuffer::new(width, height),
+                vec![0.; (width * height) as usize],
+            )
+        });
+        for (x, y, pixel) in pass_buffer.enumerate_pixels() {
+            let pixel_samples = pass_colors.samples(x, y);
+            colors[(x, y)].add_sample(pixel_samples as f64 * pass_colors.get(x, y).copy());
+            colors.set_samples(x, y, colors.samples(x, y) + pixel_samples);
+            alpha[(y * pass_buffer.width() + x) as usize] += pixel_samples as f64 * pixel[3] as f64;
+        }
+        done += samples;
+
+        for y in 0..colors.height() {
+            for x in 0..colors.width() {
+                let scale = 1. / colors.samples(x, y) as f64;
+                average.put(x, y, scale * colors.get(x, y).copy());
+                average.set_samples(x, y, colors.samples(x, y));
+            }
+        }
+        if let Some(bloom) = &config.bloom {
+            bloom.apply(&mut average);
+        }
+        for (x, y, pixel) in image_buffer.enumerate_pixels_mut() {
+            let mut color = average.get(x, y).copy();
+            color.combine_with(1., config.pipeline());
+            let [r, g, b] = color.get_u8();
+            let a = alpha[(y * colors.width() + x) as usize] / colors.samples(x, y) as f64;
+            *pixel = Rgba([r, g, b, math::round(a) as u8]);
+        }
+        on_pass(&image_buffer, done);
+    }
+
+    Ok((image_buffer, config.keep_colors.then_some(average)))
+}
+
+/// Renders the image in horizontal strips of `strip_height` lines, which are saved into numbered
+/// files derived from `filename` (see `strip_file_name`) as soon as they are finished. Only one
+/// strip is held in memory at a time.
+///
+/// Post-processing is applied to every strip separately, bloom does not cross their edges.
+fn render_strips(
+    cam: &Arc<dyn Camera>,
+    image: &Image,
+    config: &RenderConfig,
+    scene: &Arc<Scene>,
+    strip_height: u32,
+    filename: &str,
+) -> Result<(), RenderError> {
+    if config.bloom.is_some() {
+        log::warn!("Bloom is applied to every strip separately, there may be seams between them");
+    }
+    let mut config = config.clone();
+    for (index, start) in (0..image.height)
+        .step_by(strip_height.max(1) as usize)
+        .enumerate()
+    {
+        let end = (start + strip_height).min(image.height);
+        config.rows = Some(start..end);
+        let (strip, _) = calculate_image(cam, image, &config, scene)?;
+        let strip_file = strip_file_name(filename, index);
+        save_image(
+            &strip,
+            &strip_file,
+            COLOR_SPACE_METADATA.then_some(config.encoding()),
+        );
+        log::info!("Saved lines {} to {} into {}", start, end - 1, strip_file);
+    }
+    Ok(())
+}
+
+/// Inserts the index of the strip before the extension of the file, e.g. `image.0001.png`.
+fn strip_file_name(filename: &str, index: usize) -> String {
+    match filename.rsplit_once('.') {
+        Some((stem, extension)) => format!("{}.{:04}.{}", stem, index, extension),
+        None => format!("{}.{:04}", filename, index),
+    }
+}
+
+/// Encoded image and, if `config.keep_colors` is set, linear colors of its pixels.
+type Rendered = (ImageBuffer<Rgba<u8>, Vec<u8>>, Option<ColorBuffer>);
+
+/// Reason why the image cannot be rendered.
+#[derive(Debug)]
+pub enum RenderError {
+    /// Aspect ratio of the camera differs from the image, the result would be stretched.
+    AspectRatio {
+        camera: f64,
+        width: u32,
+        height: u32,
+    },
+    /// The buffer given to `calculate_image_into` does not have the dimensions of the rendered
+    /// rows.
+    BufferSize {
+        buffer: (u32, u32),
+        expected: (u32, u32),
+    },
+    /// `config.rows` reach beyond the bottom of the image.
+    Rows { rows: Range<u32>, height: u32 },
+    /// `config.samples` is 0, the pixels would have no color.
+    NoSamples,
+}
+impl Error for RenderError {}
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderError::AspectRatio {
+                camera,
+                width,
+                height,
+            } => write!(
+                f,
+                "Aspect ratio of the camera ({}) does not match the image ({}x{}).",
+                camera, width, height
+            ),
+            RenderError::BufferSize { buffer, expected } => write!(
+                f,
+                "The buffer ({}x{}) does not match the rendered rows of the image ({}x{}).",
+                buffer.0, buffer.1, expected.0, expected.1
+            ),
+            RenderError::Rows { rows, height } => write!(
+                f,
+                "Rows {:?} are out of the image of {} lines.",
+                rows, height
+            ),
+            RenderError::NoSamples => write!(f, "Every pixel needs at least one sample."),
+        }
+    }
+}
+
+/// Iterates over every pixel in the image, calculates its color and returns the resulting image.
+/// The whole computation is done in parallel (`THREAD_COUNT` constant). Linear colors of the
+/// pixels are returned as well if `config.keep_colors` is set.
+///
+/// Finished lines are saved into the checkpoint file (if configured), lines found there are not
+/// rendered again. The checkpoint is removed once the whole image is done.
+/// Progress is reported to `config.progress` (if set) after every finished line and the line itself
+/// is passed to `config.on_tile`.
+///
+/// The camera and the scene are only borrowed (worker threads get clones of the `Arc`s), so they
+/// can be built once and rendered repeatedly, e.g. with different settings.
+///
+/// With `config.rows`, only the given rows are rendered, the returned image holds just them.
+///
+/// Fails if aspect ratios of the camera and the image differ (the result would be stretched), the
+/// rows are out of the image or the pixels have no samples.
+pub fn calculate_image(
+    cam: &Arc<dyn Camera>,
+    image: &Image,
+    config: &RenderConfig,
+    scene: &Arc<Scene>,
+) -> Result<Rendered, RenderError> {
+    let rows = config.rows.clone().unwrap_or(0..image.height);
+    let mut image_buffer = ImageBuffer::new(image.width, rows.end.saturating_sub(rows.start));
+    let colors = calculate_image_into(cam, image, config, scene, &mut image_buffer)?;
+    Ok((image_buffer, colors))
+}
+
+/// Renders the image as `calculate_image` does and returns only the linear colors of the pixels
+/// (`config.keep_colors` is implied), e.g. for embedding the renderer into another program with
+/// its own tone mapping and output.
+pub fn render(
+    cam: &Arc<dyn Camera>,
+    image: &Image,
+    config: &RenderConfig,
+    scene: &Arc<Scene>,
+) -> Result<ColorBuffer, RenderError> {
+    let mut config = config.clone();
+    config.keep_colors = true;
+    let (_, colors) = calculate_image(cam, image, &config, scene)?;
+    Ok(colors.unwrap())
+}
+
+/// Renders the image as `calculate_image` does, but into the existing `image_buffer`, so that
+/// repeated renders (e.g. frames of a live preview) do not allocate a new image every time.
+/// Every pixel of the buffer is overwritten. Post-processing (supersampling and bloom) still
+/// needs its own intermediate buffers.
+///
+/// Fails as `calculate_image` does, or if the buffer does not have the dimensions of the rendered
+/// rows of the image.
+pub fn calculate_image_into(
+    cam: &Arc<dyn Camera>,
+    image: &Image,
+    config: &RenderConfig,
+    scene: &Arc<Scene>,
+    image_buffer: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+) -> Result<Option<ColorBuffer>, RenderError> {
+    if config.samples_per_pixel() == 0 {
+        return Err(RenderError::NoSamples);
+    }
+    if !image.matches_aspect_ratio(cam.aspect_ratio()) {
+        return Err(RenderError::AspectRatio {
+            camera: cam.aspect_ratio(),
+            width: image.width,
+            height: image.height,
+        });
+    }
+    // Row 0 of the buffer is the row `strip.start` of the image
+    let strip = config.rows.clone().unwrap_or(0..image.height);
+    if strip.end > image.height {
+        return Err(RenderError::Rows {
+            rows: strip,
+            height: image.height,
+        });
+    }
+    let strip_height = strip.end.saturating_sub(strip.start);
+    if image_buffer.dimensions() != (image.width, strip_height) {
+        return Err(RenderError::BufferSize {
+            buffer: image_buffer.dimensions(),
+            expected: (image.width, strip_height),
+        });
+    }
+    if config.supersampling > 1 || config.bloom.is_some() {
+        let (buffer, colors) = calculate_post_processed_image(cam, image, config, scene)?;
+        image_buffer.copy_from_slice(&buffer);
+        return Ok(colors);
+    }
+
+    let stats = Arc::new(RayStats::new());
+    let mut colors = config
+        .keep_colors
+        .then(|| ColorBuffer::new(image.width, strip_height));
+    let checkpoint_file = config
+        .checkpoint_file
+        .as_ref()
+        .filter(|_| !config.keep_colors && config.rows.is_none());
+    let mut checkpoint = checkpoint_file.and_then(|path| {
+        Checkpoint::open(path, image_buffer, checkpoint_key(cam, config, scene))
+            .map_err(|e| log::warn!("Cannot use checkpoint {}: {}", path.display(), e))
+            .ok()
+    });
+    // Lines that are not in the checkpoint, every line is one job for the thread pool
+    let rows: Vec<u32> = strip
+        .clone()
+        .filter(|&h| !checkpoint.as_ref().is_some_and(|c| c.is_done(h)))
+        .collect();
+    if let Some(c) = &checkpoint {
+        for h in strip.clone().filter(|&h| c.is_done(h)) {
+            report_tile(config, image_buffer, h - strip.start, strip.start);
+        }
+    }
+    // Nothing to render (no rows, or all of them are in the checkpoint), no pool is needed
+    if rows.is_empty() {
+        if let Some(c) = checkpoint {
+            if let Err(e) = c.remove() {
+                log::warn!("Cannot remove the checkpoint: {}", e);
+            }
+        }
+        return Ok(colors);
+    }
+    // Threads beyond the number of jobs would never get any work
+    let thread_count = THREAD_COUNT.min(rows.len().min(u8::MAX as usize) as u8);
+    if thread_count < THREAD_COUNT {
+        log::warn!(
+            "Only {} lines to render (one job per line), using {} threads instead of {}",
+            rows.len(),
+            thread_count,
+            THREAD_COUNT
+        );
+    }
+    // Jobs are run in the current thread if there is only one thread or the pool cannot be
+    // created, the results of every line are then processed as soon as it is finished
+    let pool = if thread_count > 1 {
+        ThreadPool::new(thread_count)
+            .map_err(|e| log::warn!("{} Rendering in the current thread.", e))
+            .ok()
+    } else {
+        None
+    };
+    log::info!(
+        "Rendering {} lines using {} threads",
+        rows.len(),
+        pool.as_ref().map_or(1, ThreadPool::len)
+    );
+    let rows_to_render = rows.len();
+    let start = Instant::now();
+    // Channel for transmitting results back to the main thread
+    let (sender, receiver) = mpsc::channel();
+
+    // Every thread needs to own this data
+    let image = Arc::new(*image);
+    let config = Arc::new(config.clone());
+
+    // Number of pixels to be received for every line
+    let mut remaining = vec![image.width; strip_height as usize];
+    let mut rendered_rows = 0;
+    let mut receive = |incoming: (u32, u32, Rgba<u8>, Option<(Color, u16)>)| {
+        let (w, h, image_color, linear) = incoming;
+        let y = h - strip.start;
+        image_buffer.put_pixel(w, y, image_color);
+        if let (Some(buffer), Some((color, samples))) = (colors.as_mut(), linear) {
+            buffer.put(w, y, color);
+            buffer.set_samples(w, y, samples as u32);
+        }
+
+        remaining[y as usize] -= 1;
+        if remaining[y as usize] == 0 {
+            rendered_rows += 1;
+            if let Some(progress) = &config.progress {
+                let done = strip_height as usize - rows_to_render + rendered_rows;
+                progress(Progress {
+                    fraction: done as f64 / strip_height as f64,
+                    remaining: start
+                        .elapsed()
+                        .mul_f64((rows_to_render - rendered_rows) as f64 / rendered_rows as f64),
+                });
+            }
+            report_tile(&config, image_buffer, y, strip.start);
+            if let Some(c) = checkpoint.as_mut() {
+                if let Err(e) = c.save_row(y, image_buffer) {
+                    log::warn!("Cannot save line {} to the checkpoint: {}", h, e);
+                }
+            }
+        }
+    };
+
+    // `h` and `w` give us location of the pixel in the image
+    for h in rows {
+        let cam_clone = cam.clone();
+        let image_clone = image.clone();
+        let config_clone = config.clone();
+        let scene_clone = scene.clone();
+        let stats_clone = stats.clone();
+        let sender_clone = sender.clone();
+
+        let job = move || {
+            for w in 0..image_clone.width {
+                let (mut color, weight, samples, coverage) = get_pixel_color(
+                    &cam_clone,
+                    &image_clone,
+                    &config_clone,
+                    &scene_clone,
+                    &stats_clone,
+                    h,
+                    w,
+                );
+                let linear = config_clone
+                    .keep_colors
+                    .then(|| ((1. / weight) * color.copy(), samples));
+                color.combine_with(weight, config_clone.pipeline());
+                let [r, g, b] = color.get_u8();
+                let alpha = if config_clone.transparent_background {
+                    math::round(255. * coverage) as u8
+                } else {
+                    255
+                };
+                let image_color = image::Rgba([r, g, b, alpha]);
+
+                let tuple = (w, h, image_color, linear);
+                // The receiver is gone only if the render was abandoned, the rest of the line is
+                // not needed then
+                if sender_clone.send(tuple).is_err() {
+                    log::debug!("Rendering of line {} cancelled", h);
+                    return;
+                }
+            }
+            log::debug!("Finished rendering of line {}", h);
+        };
+        match &pool {
+            Some(pool) => pool.execute(job),
+            None => {
+                job();
+                receiver.try_iter().for_each(&mut receive);
+            }
+        }
+    }
+    // The original value has to be dropped, so that the receiving for loop below ends after all
+    // threads finish their work.
+    std::mem::drop(sender);
+    receiver.into_iter().for_each(receive);
+
+    if let Some(c) = checkpoint {
+        if let Err(e) = c.remove() {
+            log::warn!("Cannot remove the checkpoint: {}", e);
+        }
+    }
+    stats.report();
+
+    Ok(colors)
+}
+
+/// Fingerprint of what the finished lines of the checkpoint depend on besides the dimensions of
+/// the image: the render settings, the color pipeline (by its results for a few probe colors),
+/// the aspect ratio of the camera and the number of objects and bounds of the scene. Other changes
+/// (e.g. of materials, the background or the position of the camera) are not detected, the
+/// checkpoint has to be removed by hand then.
+fn checkpoint_key(cam: &Arc<dyn Camera>, config: &RenderConfig, scene: &Scene) -> u64 {
+    let probes: Vec<Color> = [0.01, 0.2, 0.5, 1., 4.]
+        .iter()
+        .map(|&v| config.pipeline().apply(Color::new(v, v, v)))
+        .collect();
+    let sampling = (
+        config.seed,
+        config.samples,
+        config.first_sample,
+        config.center_sample,
+        config.filter,
+        config.integrator,
+        config.indirect_clamp,
+        MAX_DEPTH,
+    );
+    let image = (
+        config.near_clip,
+        config.far_clip,
+        config.flip_y,
+        config.transparent_background,
+        probes,
+    );
+    let description = format!(
+        "{:?}",
+        (
+            sampling,
+            image,
+            cam.aspect_ratio(),
+            scene.len(),
+            scene.bounding_box()
+        )
+    );
+    let mut hasher = DefaultHasher::new();
+    description.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Passes row `y` of `buffer`, which holds rows of the image starting at `first_row`, to the tile
+/// callback of the `config`.
+fn report_tile(
+    config: &RenderConfig,
+    buffer: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    y: u32,
+    first_row: u32,
+) {
+    if let Some(on_tile) = &config.on_tile {
+        let row_size = 4 * buffer.width() as usize;
+        let start = y as usize * row_size;
+        on_tile(Tile {
+            x: 0,
+            y: first_row + y,
+            width: buffer.width(),
+            height: 1,
+            pixels: &buffer.as_raw()[start..start + row_size],
+        });
+    }
+}
+
+/// Renders linear colors of the image first and encodes them after post-processing:
+/// * with `config.supersampling`, the image is rendered at larger resolution and scaled down,
+///   every pixel is the average of a `supersampling × supersampling` block of linear colors,
+/// * `config.bloom` is applied to the scaled down image.
+fn calculate_post_processed_image(
+    cam: &Arc<dyn Camera>,
+    image: &Image,
+    config: &RenderConfig,
+    scene: &Arc<Scene>,
+) -> Result<Rendered, RenderError> {
+    let scale = config.supersampling.max(1);
+    let large_image = Image {
+        width: image.width * scale,
+        height: image.height * scale,
+        pixel_aspect_ratio: image.pixel_aspect_ratio,
+    };
+    let keep_colors = config.keep_colors;
+    let mut config = config.clone();
+    let bloom = config.bloom.take();
+    // Lines of the large image are not the lines of the result, tiles are reported at the end
+    let on_tile = config.on_tile.take();
+    config.keep_colors = true;
+    config.supersampling = 1;
+    let strip = config.rows.take().unwrap_or(0..image.height);
+    config.rows = Some(strip.start * scale..strip.end * scale);
+    let strip_height = strip.end.saturating_sub(strip.start);
+    let (large_buffer, large_colors) = calculate_image(cam, &large_image, &config, scene)?;
+    let large_colors = large_colors.unwrap();
+
+    let mut image_buffer: ImageBuffer<Rgba<u8>, Vec<u8>> =
+        ImageBuffer::new(image.width, strip_height);
+    let mut colors = ColorBuffer::new(image.width, strip_height);
+    let block_size = scale * scale;
+    for (x, y, pixel) in image_buffer.enumerate_pixels_mut() {
+        let mut color = Color::black();
+        let mut alpha = 0;
+        let mut samples = 0;
+        for j in y * scale..(y + 1) * scale {
+            for i in x * scale..(x + 1) * scale {
+                color.add_sample(large_colors.get(i, j).copy());
+                alpha += large_buffer.get_pixel(i, j)[3] as u32;
+                samples += large_colors.samples(i, j);
+            }
+        }
+        colors.put(x, y, (1. / block_size as f64) * color);
+        colors.set_samples(x, y, samples);
+        pixel[3] = (alpha / block_size) as u8;
+    }
+
+    if let Some(bloom) = bloom {
+        bloom.apply(&mut colors);
+    }
+
+    for (x, y, pixel) in image_buffer.enumerate_pixels_mut() {
+        let mut color = colors.get(x, y).copy();
+        color.combine_with(1., config.pipeline());
+        let [r, g, b] = color.get_u8();
+        *pixel = Rgba([r, g, b, pixel[3]]);
+    }
+    config.on_tile = on_tile;
+    for y in 0..strip_height {
+        report_tile(&config, &image_buffer, y, strip.start);
+    }
+
+    Ok((image_buffer, keep_colors.then_some(colors)))
+}
+
+/// Renders the single pixel at column `x` and row `y` (from the top) in the current thread, every
+/// sample and every bounce of its paths (hit point, normal, emitted light, scattered direction,
+/// attenuation) is logged by `log::debug!`. Returns the linear color of the pixel, which is logged
+/// as well.
+pub fn debug_pixel(
+    cam: &Arc<dyn Camera>,
+    image: &Image,
+    config: &RenderConfig,
+    scene: &Arc<Scene>,
+    x: u32,
+    y: u32,
+) -> Color {
+    let mut config = config.clone();
+    config.trace = true;
+    config.deadline = None;
+    let stats = RayStats::new();
+    let (color, weight, samples, coverage) = get_pixel_color(
+        cam,
+        &Arc::new(*image),
+        &Arc::new(config),
+        scene,
+        &stats,
+        y,
+        x,
+    );
+    let color = (1. / weight) * color;
+    log::debug!(
+        "Pixel ({}, {}): {} from {} samples, coverage {}",
+        x,
+        y,
+        color,
+        samples,
+        coverage
+    );
+    color
+}
+
+/// Computes color of the pixel at coordinates `w` and `h`. Uses two offset vectors `u` and `v` to convert
+/// the image pixel location to a fraction from 0 to 1 (used with virtual viewport for ray calculation).
+///
+/// Uses Supersampling anti-aliasing with random algorithm (stochastic sampling), unless
+/// `config.center_sample` is set. The samples are summed up weighted by `config.filter`, they
+/// are combined by the caller (see `Color::combine_weighted`).
+///
+/// Once `config.deadline` passes, no more samples are taken (but every pixel gets at least one).
+///
+/// Also returns the sum of the weights, the number of samples and the (weighted) fraction of the
+/// camera rays that hit an object (coverage of the pixel), which gives anti-aliased edges of
+/// objects in the alpha channel.
+fn get_pixel_color(
+    cam_clone: &Arc<dyn Camera>,
+    image_clone: &Arc<Image>,
+    config_clone: &Arc<RenderConfig>,
+    scene_clone: &Arc<Scene>,
+    stats: &RayStats,
+    h: u32,
+    w: u32,
+) -> (Color, f64, u16, f64) {
+    let mut color = Color::black();
+    let mut weights = 0.;
+    let mut samples = 0;
+    let mut hits = 0.;
+    let mut unweighted = Color::black();
+    let mut unweighted_hits = 0;
+    // The center sample must not depend on random numbers left by the previous pixel
+    let seed = config_clone
+        .seed
+        .or_else(|| config_clone.center_sample.then_some(0));
+    for i in 0..config_clone.samples_per_pixel() {
+        if i > 0 && config_clone.deadline.is_some_and(|d| Instant::now() >= d) {
+            break;
+        }
+        if let Some(seed) = seed {
+            // Every sample has its own stream of random numbers
+            let pixel = h as u64 * image_clone.width as u64 + w as u64;
+            let index = config_clone.first_sample as u64 + i as u64;
+            random::seed(seed, pixel << 32 | index);
+        }
+        // The viewport has its origin in the lower left corner, rows of the image go from the top
+        // unless the image is flipped.
+        let row = if config_clone.flip_y {
+            h as f64
+        } else {
+            image_clone.height as f64 - 1. - h as f64
+        };
+        // Offsets from the lower left corner of the pixel, they cover the square of the filter
+        let (offset_u, offset_v) = if config_clone.center_sample {
+            (0.5, 0.5)
+        } else {
+            let radius = config_clone.filter.radius();
+            (
+                0.5 - radius + 2. * radius * random_double(),
+                0.5 - radius + 2. * radius * random_double(),
+            )
+        };
+        let u: f64 = (w as f64 + offset_u) / (image_clone.width as f64 - 1.0);
+        let v: f64 = (row + offset_v) / (image_clone.height as f64 - 1.0);
+
+        let ray = cam_clone.calculate_ray(u, v);
+        // Directions of camera rays are not normalized, clipping distances are converted to the
+        // parameter `t` of the ray.
+        let length = ray.direction().length();
+        let t_range = config_clone.near_clip / length..config_clone.far_clip / length;
+        let sample = match config_clone.integrator {
+            Integrator::PathTracing | Integrator::BounceTint => calculate_color(
+                ray,
+                scene_clone,
+                stats,
+                config_clone,
+                MAX_DEPTH,
+                t_range,
+                true,
+            ),
+            Integrator::AmbientOcclusion { samples, radius } => ambient_occlusion(
+                &ray,
+                scene_clone,
+                stats,
+                config_clone,
+                t_range,
+                samples,
+                radius,
+            ),
+            Integrator::Normals => {
+                shade_first_hit(&ray, scene_clone, stats, config_clone, t_range, |_, rec| {
+                    let n = rec.normal;
+                    0.5 * Color::new(n.x() + 1., n.y() + 1., n.z() + 1.)
+                })
+            }
+            Integrator::Albedo => shade_first_hit(
+                &ray,
+                scene_clone,
+                stats,
+                config_clone,
+                t_range,
+                |object, rec| {
+                    if object.is_emissive() {
+                        object.emitted(rec, &ray).limited(1.)
+                    } else {
+                        object.attenuation(rec)
+                    }
+                },
+            ),
+        };
+        stats.record_path(sample.bounces, sample.bounces == MAX_DEPTH);
+        let weight = config_clone.filter.weight(offset_u - 0.5, offset_v - 0.5);
+        if config_clone.trace {
+            log::debug!(
+                "Sample {} at ({}, {}) of the pixel: {} after {} bounces, weight {}",
+                i,
+                offset_u,
+                offset_v,
+                sample.color,
+                sample.bounces,
+                weight
+            );
+        }
+        color.add_sample(weight * sample.color.copy());
+        unweighted.add_sample(sample.color);
+        weights += weight;
+        samples += 1;
+        if sample.hit {
+            hits += weight;
+            unweighted_hits += 1;
+        }
+    }
+    // All samples fell where the filter is zero, they are averaged with equal weights instead
+    if weights <= 0. {
+        let count = samples as f64;
+        return (unweighted, count, samples, unweighted_hits as f64 / count);
+    }
+    (color, weights, samples, hits / weights)
+}
+
+/// Result of tracing a single ray by `calculate_color`.
+struct TraceResult {
+    color: Color,
+    /// `true` if the ray hit an object, `false` if it escaped to the background.
+    hit: bool,
+    /// Number of times the path was scattered before it terminated (escaped to the background,
+    /// was absorbed or reached the depth limit). It equals the initial `depth` only if the path
+    /// was cut off by the depth limit.
+    bounces: u16,
+}
+
+/// This returns color based on the surface normal vector at the collision point with an object (or
+/// multiple collisions) or background color (given by `config`).
+///
+/// Only intersections within `t_range` are considered. Intersections closer than its start are
+/// ignored, so that a ray scattered from a surface does not hit the same surface again due to
+/// floating point errors. The distance is advised by the material of the surface
+/// (`Material::ray_epsilon`) and divided by the length of the scattered direction, which is not
+/// necessarily normalized.
+///
+/// Light emitted by scene lights is added only if `count_emitted` is `true`. It is `false` for
+/// rays scattered from diffuse surfaces, because the light has already been counted by the shadow
+/// rays (see `sample_lights`). Emissive objects which cannot be sampled are not scene lights, their
+/// light is always added.
+///
+/// Rays with NaN or infinite components (e.g. from a broken material) are not traced, they escape
+/// without bringing any light, so that they do not spoil the whole pixel. They are counted and
+/// reported after the render.
+fn calculate_color(
+    ray: Ray,
+    scene: &Scene,
+    stats: &RayStats,
+    config: &Arc<RenderConfig>,
+    depth: u16,
+    t_range: Range<f64>,
+    count_emitted: bool,
+) -> TraceResult {
+    if depth == 0 {
+        if config.trace {
+            log::debug!("Depth limit reached");
+        }
+        return TraceResult {
+            color: Color::black(),
+            hit: false,
+            bounces: 0,
+        };
+    }
+    if !ray.is_finite() {
+        if config.trace {
+            log::debug!(
+                "Invalid ray from {} in direction {}",
+                ray.origin(),
+                ray.direction()
+            );
+        }
+        stats.record_invalid_ray();
+        return TraceResult {
+            color: Color::black(),
+            hit: false,
+            bounces: 0,
+        };
+    }
+
+    // https://raytracing.github.io/books/RayTracingInOneWeekend.html#diffusematerials/
+    if let Some((s, rec)) = nearest_hit(&ray, scene.objects(), t_range.start, t_range.end, stats) {
+        let emitted = if count_emitted || !s.can_be_sampled() {
+            s.emitted(&rec, &ray)
+        } else {
+            Color::black()
+        };
+        if config.trace {
+            log::debug!(
+                "Bounce {}: hit at {} (t = {}), normal {}, emitted {}",
+                MAX_DEPTH - depth,
+                rec.point,
+                rec.t,
+                rec.normal,
+                emitted
+            );
+        }
+        let bounce_tint = config.integrator == Integrator::BounceTint;
+        let sample_direct = s.is_diffuse()
+            && !bounce_tint
+            && (!scene.lights().is_empty() || config.environment.is_some());
+
+        let (color, bounces) = match s.scatter(&rec, &ray) {
+            Some(new_ray) => {
+                let epsilon = s.ray_epsilon();
+                let t_min = epsilon / new_ray.direction().length();
+                let attenuation = if bounce_tint {
+                    bounce_color(MAX_DEPTH - depth)
+                } else {
+                    s.reflectance(&rec, &ray, new_ray.direction())
+                };
+                if config.trace {
+                    log::debug!(
+                        "Scattered in direction {}, attenuation {}",
+                        new_ray.direction(),
+                        attenuation
+                    );
+                }
+                let scattered = calculate_color(
+                    new_ray,
+                    scene,
+                    stats,
+                    config,
+                    depth - 1,
+                    t_min..INFINITY,
+                    !sample_direct,
+                );
+                let mut color = attenuation * scattered.color;
+                if let Some(max) = config.indirect_clamp {
+                    color = color.limited(max);
+                }
+                if sample_direct {
+                    let direct = sample_lights(&rec, &ray, s, epsilon, scene, stats, config);
+                    if config.trace {
+                        log::debug!("Direct light from shadow rays {}", direct);
+                    }
+                    color = color + direct;
+                }
+                (emitted + color, scattered.bounces + 1)
+            }
+            None => {
+                if config.trace {
+                    log::debug!("Absorbed (not scattered)");
+                }
+                (emitted, 0)
+            }
+        };
+        if config.trace {
+            log::debug!("Bounce {}: color {}", MAX_DEPTH - depth, color);
+        }
+        return TraceResult {
+            color,
+            hit: true,
+            bounces,
+        };
+    }
+    // Light of the environment has already been counted by the shadow rays, as the emitted light
+    let color = match &config.environment {
+        Some(_) if !count_emitted => Color::black(),
+        _ => background(config, &ray),
+    };
+    if config.trace {
+        log::debug!(
+            "Escaped in direction {}, background {}",
+            ray.direction(),
+            color
+        );
+    }
+    TraceResult {
+        color,
+        hit: false,
+        bounces: 0,
+    }
+}
+
+/// Returns the tint of the light reflected by the surface hit after `bounce` previous bounces
+/// (0 for the surface seen by the camera), see `Integrator::BounceTint`. The tints are light, so
+/// that their products along a path do not turn black.
+fn bounce_color(bounce: u16) -> Color {
+    let palette = [Color::red(), Color::green(), Color::new(0., 0., 1.)];
+    Color::lerp(
+        &Color::white(),
+        &palette[bounce as usize % palette.len()],
+        0.5,
+    )
+}
+
+/// Returns the color of the ray that escaped from the scene.
+fn background(config: &RenderConfig, ray: &Ray) -> Color {
+    match &config.environment {
+        Some(environment) => environment.value(ray.direction()),
+        None => (config.background)(ray),
+    }
+}
+
+/// Shades the first surface hit by the ray by how much it is occluded by nearby objects. Rays are
+/// sent from the hit point with cosine distribution around the normal, the color is the fraction
+/// of them that does not hit anything within `radius`. Materials and lights are ignored.
+fn ambient_occlusion(
+    ray: &Ray,
+    scene: &Scene,
+    stats: &RayStats,
+    config: &Arc<RenderConfig>,
+    t_range: Range<f64>,
+    samples: u16,
+    radius: f64,
+) -> TraceResult {
+    let (s, rec) = match nearest_hit(ray, scene.objects(), t_range.start, t_range.end, stats) {
+        Some(hit) => hit,
+        None => {
+            return TraceResult {
+                color: background(config, ray),
+                hit: false,
+                bounces: 0,
+            }
+        }
+    };
+
+    // Normals point outwards, rays hitting the inside of a surface need the opposite one
+    let normal = if Vec3::dot(rec.normal, ray.direction()) > 0. {
+        -rec.normal
+    } else {
+        rec.normal
+    };
+    let epsilon = s.ray_epsilon();
+    let unoccluded = (0..samples)
+        .filter(|_| {
+            let direction = material::diffuse_direction(normal);
+            let occlusion_ray = Ray::new_at_time(rec.point, direction.unit(), ray.time());
+            !hit_any(scene, &occlusion_ray, epsilon, radius, stats)
+        })
+        .count();
+
+    TraceResult {
+        color: (unoccluded as f64 / samples.max(1) as f64) * Color::white(),
+        hit: true,
+        bounces: 0,
+    }
+}
+
+/// Colors the first surface hit by the ray by `shade` (e.g. by its normal, see
+/// `Integrator::Normals`), rays that miss the scene get the background.
+fn shade_first_hit<F>(
+    ray: &Ray,
+    scene: &Scene,
+    stats: &RayStats,
+    config: &RenderConfig,
+    t_range: Range<f64>,
+    shade: F,
+) -> TraceResult
+where
+    F: Fn(&dyn TraceableObjects, &HitRecord) -> Color,
+{
+    match nearest_hit(ray, scene.objects(), t_range.start, t_range.end, stats) {
+        Some((object, rec)) => TraceResult {
+            color: shade(object, &rec),
+            hit: true,
+            bounces: 0,
+        },
+        None => TraceResult {
+            color: background(config, ray),
+            hit: false,
+            bounces: 0,
+        },
+    }
+}
+
+/// Returns the object closest to the ray origin (on interval (t_min, t_max)) together with the
+/// intersection data.
+///
+/// If more objects are hit at the same distance (e.g. coincident surfaces), the one that comes
+/// first in `shapes` is returned, so the same scene is always rendered the same way.
+fn nearest_hit<'a>(
+    ray: &Ray,
+    shapes: &'a [Arc<dyn TraceableObjects>],
+    t_min: f64,
+    t_max: f64,
+    stats: &RayStats,
+) -> Option<(&'a dyn TraceableObjects, HitRecord)> {
+    let mut closest = None;
+    let mut closest_t = t_max;
+    // Two records are reused for the whole traversal, the closest hit so far and the tested one
+    let mut rec = HitRecord::new();
+    let mut candidate = HitRecord::new();
+    for s in shapes {
+        candidate.reset();
+        // Objects accept hits at `t_max` too, the strict comparison keeps the earlier object
+        if s.hit(ray, t_min, closest_t, &mut candidate) && candidate.t < closest_t {
+            closest_t = candidate.t;
+            std::mem::swap(&mut rec, &mut candidate);
+            closest = Some(s.as_ref());
+        }
+    }
+    stats.record_traversal(shapes.len() as u64);
+    closest.map(|s| (s, rec))
+}
+
+/// Returns `true` if the ray hits any object of the scene on interval (t_min, t_max), see
+/// `Scene::hit_any`. The traversal is recorded into `stats`.
+fn hit_any(scene: &Scene, ray: &Ray, t_min: f64, t_max: f64, stats: &RayStats) -> bool {
+    let (hit, tests) = scene.hit_any_counted(ray, t_min, t_max);
+    stats.record_traversal(tests);
+    hit
+}
+
+/// Computes direct light coming to the diffuse surface from scene lights. One shadow ray is aimed
+/// at a random point of every light, the light contributes only if nothing blocks the ray (see
+/// `Scene::hit_any`). Another one is aimed at the environment map (if any) in a direction chosen
+/// by its brightness.
+///
+/// More thorough explanation can be found at:
+/// https://raytracing.github.io/books/RayTracingTheRestOfYourLife.html#samplinglightsdirectly
+fn sample_lights(
+    rec: &HitRecord,
+    ray_in: &Ray,
+    material: &dyn TraceableObjects,
+    epsilon: f64,
+    scene: &Scene,
+    stats: &RayStats,
+    config: &RenderConfig,
+) -> Color {
+    let mut color = Color::black();
+    if let Some(environment) = &config.environment {
+        let direction = environment.sample();
+        let cosine = Vec3::dot(rec.normal, direction);
+        let pdf = environment.pdf_value(direction);
+        let shadow_ray = Ray::new_at_time(rec.point, direction, ray_in.time());
+        if cosine > 0. && pdf > 0. && !hit_any(scene, &shadow_ray, epsilon, INFINITY, stats) {
+            let incoming = cosine / (std::f64::consts::PI * pdf) * environment.value(direction);
+            color.add_sample(material.reflectance(rec, ray_in, direction) * incoming);
+        }
+    }
+    for light in scene.lights() {
+        // The direction is normalized, so that `t` of the shadow ray is the distance from the
+        // surface and the sampled point lies at t = `distance`
+        let to_light = light.random_point(rec.point) - rec.point;
+        let distance = to_light.length();
+        if distance <= epsilon {
+            continue;
+        }
+        let direction = to_light / distance;
+        let cosine = Vec3::dot(rec.normal, direction);
+        if cosine <= 0. {
+            continue;
+        }
+        let pdf = light.pdf_value(rec.point, direction);
+        if pdf <= 0. {
+            continue;
+        }
+
+        let shadow_ray = Ray::new_at_time(rec.point, direction, ray_in.time());
+        // Anything in front of the sampled point blocks the light
+        if hit_any(scene, &shadow_ray, epsilon, distance - epsilon, stats) {
+            continue;
+        }
+        let mut hit = HitRecord::new();
+        if light.hit(
+            &shadow_ray,
+            distance - epsilon,
+            distance + epsilon,
+            &mut hit,
+        ) {
+            // BRDF is the reflectance / pi (albedo / pi for Lambertian materials)
+            let emitted = cosine / (std::f64::consts::PI * pdf) * light.emitted(&hit, &shadow_ray);
+            color.add_sample(material.reflectance(rec, ray_in, direction) * emitted);
+        }
+    }
+    color
+}
+
+/// Returns linearly blended color depending on the ray coordinates.
+fn linearly_blend_colors(r: &Ray, start_value: Color, end_value: Color) -> Color {
+    // Normalizing the vector => as value of y changes, the value of x has to change too =>
+    // resulting color is dependent on both coordinates
+    let unit_direction: Vec3 = r.unit_vector();
+
+    // Transform from ( -1.0 ... 1.0) to ( 0.0 ... 1.0)
+    let t = 0.5 * (unit_direction.y() + 1.0);
+
+    Color::lerp(&start_value, &end_value, t)
+}
+
+/// Returns random number in range from 0.0 (included) to 1.0 (excluded)
+fn random_double() -> f64 {
+    random::random_range(0.0, 1.0)
+}
+
+/// Saves the image in the format given by the extension of `filename`. PNG images get chunks
+/// describing the `encoding` of the colors, if it is given.
+fn save_image(
+    image_buffer: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    filename: &str,
+    encoding: Option<Encoding>,
+) {
+    let is_png = Path::new(filename)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("png"));
+    match encoding.filter(|_| is_png) {
+        Some(encoding) => save_png(image_buffer, filename, encoding).unwrap(),
+        None => image_buffer.save(filename).unwrap(),
+    }
+}
+
+/// Saves linear `colors` as a Radiance HDR image, negative components are saved as 0.
+fn save_hdr(colors: &ColorBuffer, filename: &str) -> image::ImageResult<()> {
+    let pixels: Vec<Rgb<f32>> = colors
+        .iter()
+        .map(|c| {
+            Rgb([
+                c.r().max(0.) as f32,
+                c.g().max(0.) as f32,
+                c.b().max(0.) as f32,
+            ])
+        })
+        .collect();
+    let file = BufWriter::new(File::create(filename)?);
+    HdrEncoder::new(file).encode(&pixels, colors.width() as usize, colors.height() as usize)
+}
+
+/// Saves the image as PNG with an sRGB chunk for `Encoding::Srgb` and a gAMA chunk (the exponent
+/// of the encoding) for all encodings, viewers without color management use the latter.
+fn save_png(
+    image_buffer: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    filename: &str,
+    encoding: Encoding,
+) -> Result<(), png::EncodingError> {
+    let file = BufWriter::new(File::create(filename)?);
+    let mut encoder = png::Encoder::new(file, image_buffer.width(), image_buffer.height());
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+
+    // gAMA holds the exponent of the encoding multiplied by 100000, sRGB is approximated by
+    // 1 / 2.2 (the value the PNG specification requires next to the sRGB chunk)
+    let gamma: u32 = match encoding {
+        Encoding::Srgb => 45455,
+        Encoding::Gamma2 => 50000,
+        Encoding::Linear => 100000,
+    };
+    if encoding == Encoding::Srgb {
+        // Rendering intent 0 (perceptual)
+        writer.write_chunk(*b"sRGB", &[0])?;
+    }
+    writer.write_chunk(*b"gAMA", &gamma.to_be_bytes())?;
+    writer.write_image_data(image_buffer.as_raw())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::Capsule;
+    use crate::Hittable;
+    use material::DiffuseLight;
+
+    /// Deterministic settings.
+    fn test_config() -> RenderConfig {
+        let mut config = RenderConfig::new();
+        config.seed = Some(1);
+        config.samples = 4;
+        config
+    }
+
+    /// Image with square pixels and a camera of the same aspect ratio at the origin looking along
+    /// -Z, with the viewport 2 units high at distance 1.
+    fn test_view(width: u32, aspect_ratio: f64) -> (Image, Arc<dyn Camera>) {
+        let image = Image::new(width, aspect_ratio, 1.);
+        let cam = Arc::new(PerspectiveCamera::new(2., image.aspect_ratio(), 1.));
+        (image, cam)
+    }
+
+    /// Sky that is red above the horizon and black below it.
+    fn red_sky(ray: &Ray) -> Color {
+        if ray.direction().y() > 0. {
+            Color::red()
+        } else {
+            Color::black()
+        }
+    }
+
+    fn render_sky(flip_y: bool) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let (image, cam) = test_view(16, 2.);
+        let mut config = test_config();
+        config.background = Arc::new(red_sky);
+        config.flip_y = flip_y;
+        calculate_image(&cam, &image, &config, &Arc::new(Scene::new()))
+            .unwrap()
+            .0
+    }
+
+    #[test]
+    fn long_focal_length_renders_distant_object_in_the_center() {
+        let image = Image::new(16, 1., 1.);
+        // Field of view of about 1.1°, the light is 100 units away and covers half of the height
+        let cam: Arc<dyn Camera> = Arc::new(PerspectiveCamera::new(2., 1., 100.));
+        let mut scene = Scene::new();
+        let light = Box::new(DiffuseLight::new(Color::white(), 1.));
+        scene.add(Sphere::at(0., 0., -100., 0.5, light));
+        let mut config = test_config();
+        config.background = Arc::new(|_| Color::black());
+        let (buffer, _) = calculate_image(&cam, &image, &config, &Arc::new(scene)).unwrap();
+
+        assert_eq!(buffer.get_pixel(8, 8), &Rgba([255, 255, 255, 255]));
+        assert_eq!(buffer.get_pixel(0, 0), &Rgba([0, 0, 0, 255]));
+        assert_eq!(buffer.get_pixel(15, 15), &Rgba([0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn debug_pixel_returns_the_color_of_the_pixel() {
+        let (image, cam) = test_view(16, 2.);
+        let mut config = test_config();
+        config.background = Arc::new(red_sky);
+        let scene = Arc::new(Scene::new());
+
+        let top = debug_pixel(&cam, &image, &config, &scene, 3, 0);
+        let bottom = debug_pixel(&cam, &image, &config, &scene, 3, image.height() - 1);
+        assert!(top.approx_eq(&Color::red(), 1e-12));
+        assert!(bottom.approx_eq(&Color::black(), 1e-12));
+    }
+
+    #[test]
+    fn camera_with_other_aspect_ratio_is_an_error() {
+        // The camera covers twice as much horizontally as vertically
+        let image = Image::new(64, 16. / 9., 1.);
+        let cam: Arc<dyn Camera> = Arc::new(PerspectiveCamera::new(2., 2., 1.));
+        let result = calculate_image(&cam, &image, &test_config(), &Arc::new(Scene::new()));
+        assert!(matches!(result, Err(RenderError::AspectRatio { .. })));
+    }
+
+    #[test]
+    fn normals_are_not_gamma_encoded() {
+        let (image, cam) = test_view(8, 1.);
+        let mut scene = Scene::new();
+        let gray = Box::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        scene.add(Sphere::at(0., 0., -5., 2., gray));
+        let mut config = test_config();
+        config.integrator = Integrator::Normals;
+        config.keep_colors = true;
+        let (buffer, colors) = calculate_image(&cam, &image, &config, &Arc::new(scene)).unwrap();
+
+        // Mapped normals are only quantized
+        let normal = colors.unwrap().get(4, 4).copy();
+        let quantize = |x: f64| (256. * x.min(0.999)) as u8;
+        let [r, g, b, _] = buffer.get_pixel(4, 4).0;
+        assert_eq!(
+            [r, g, b],
+            [normal.r(), normal.g(), normal.b()].map(quantize)
+        );
+        assert!(normal.b() > 0.9);
+        assert_eq!(config.encoding(), Encoding::Linear);
+    }
+
+    #[test]
+    fn zero_samples_is_an_error() {
+        let (image, cam) = test_view(8, 2.);
+        let mut config = test_config();
+        config.samples = 0;
+        let scene = Arc::new(Scene::new());
+
+        let result = calculate_image(&cam, &image, &config, &scene);
+        assert!(matches!(result, Err(RenderError::NoSamples)));
+        let result = render_progressive(&cam, &image, &config, &scene, |_, _| {});
+        assert!(matches!(result, Err(RenderError::NoSamples)));
+        // The center sample does not depend on the number of samples
+        config.center_sample = true;
+        assert!(calculate_image(&cam, &image, &config, &scene).is_ok());
+    }
+
+    #[test]
+    fn empty_rows_render_nothing() {
+        let (image, cam) = test_view(8, 2.);
+        let mut config = test_config();
+        config.rows = Some(2..2);
+        config.keep_colors = true;
+        let (buffer, colors) =
+            calculate_image(&cam, &image, &config, &Arc::new(Scene::new())).unwrap();
+        assert_eq!(buffer.dimensions(), (image.width(), 0));
+        assert_eq!(colors.unwrap().height(), 0);
+    }
+
+    #[test]
+    fn wide_filter_takes_samples_from_neighbouring_pixels() {
+        // The horizon is in the middle of row 4, row 5 is black unless the filter reaches row 4
+        let (image, cam) = test_view(16, 2.);
+        let mut config = test_config();
+        config.background = Arc::new(red_sky);
+        config.keep_colors = true;
+        config.samples = 64;
+        let scene = Arc::new(Scene::new());
+        let sharp = render(&cam, &image, &config, &scene).unwrap();
+        config.filter = PixelFilter::Tent { radius: 1.5 };
+        let wide = render(&cam, &image, &config, &scene).unwrap();
+
+        assert_eq!(sharp.get(5, 5).r(), 0.);
+        assert!(wide.get(5, 5).r() > 0.01);
+        assert!(wide.get(5, 5).r() < 0.5);
+    }
+
+    #[test]
+    fn progressive_render_applies_bloom_once() {
+        let (image, cam) = test_view(16, 2.);
+        let mut config = test_config();
+        config.background = Arc::new(|ray: &Ray| {
+            if ray.direction().y() > 0. {
+                Color::new(4., 4., 4.)
+            } else {
+                Color::black()
+            }
+        });
+        config.bloom = Some(Bloom::new(1., 2., 0.5));
+        config.keep_colors = true;
+        let scene = Arc::new(Scene::new());
+        let (_, direct) = calculate_image(&cam, &image, &config, &scene).unwrap();
+        let (_, progressive) =
+            render_progressive(&cam, &image, &config, &scene, |_, _| {}).unwrap();
+        let (direct, progressive) = (direct.unwrap(), progressive.unwrap());
+
+        for y in 0..image.height() {
+            for x in 0..image.width() {
+                assert!(progressive.get(x, y).approx_eq(direct.get(x, y), 1e-9));
+            }
+        }
+    }
+
+    #[test]
+    fn capsule_light_illuminates_diffuse_surface() {
+        let (image, cam) = test_view(8, 1.);
+        let mut scene = Scene::new();
+        // Wall facing the camera at z = -5 and a light above the field of view
+        let gray = Box::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        scene.add(Sphere::at(0., 0., -1005., 1000., gray));
+        let light = Box::new(DiffuseLight::new(Color::white(), 4.));
+        scene.add(Capsule::new(
+            Point::new(-1., 6., -3.),
+            Point::new(1., 6., -3.),
+            0.5,
+            light,
+        ));
+        let mut config = test_config();
+        config.background = Arc::new(|_| Color::black());
+        let colors = render(&cam, &image, &config, &Arc::new(scene)).unwrap();
+
+        assert!(colors.get(4, 4).r() > 0.01);
+    }
+
+    /// Emissive sphere which keeps the defaults of `Hittable`, it cannot be sampled.
+    struct Glow(Sphere);
+
+    impl Hittable for Glow {
+        fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+            self.0.hit(ray, t_min, t_max, rec)
+        }
+    }
+
+    impl Material for Glow {
+        fn scatter(&self, rec: &HitRecord, ray_in: &Ray) -> Option<Ray> {
+            self.0.scatter(rec, ray_in)
+        }
+
+        fn attenuation(&self, rec: &HitRecord) -> Color {
+            self.0.attenuation(rec)
+        }
+
+        fn emitted(&self, rec: &HitRecord, ray_in: &Ray) -> Color {
+            self.0.emitted(rec, ray_in)
+        }
+
+        fn is_emissive(&self) -> bool {
+            true
+        }
+    }
+
+    impl TraceableObjects for Glow {}
+
+    #[test]
+    fn light_which_cannot_be_sampled_illuminates_diffuse_surface() {
+        let (image, cam) = test_view(8, 1.);
+        let mut config = test_config();
+        config.background = Arc::new(|_| Color::black());
+        // Wall facing the camera at z = -5 and a small light that can be sampled, so that the
+        // light of the wall is found by shadow rays
+        let scene = |glow: bool| {
+            let mut scene = Scene::new();
+            let gray = Box::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+            scene.add(Sphere::at(0., 0., -1005., 1000., gray));
+            let light = Box::new(DiffuseLight::new(Color::white(), 1.));
+            scene.add(Sphere::at(0., 20., -3., 0.1, light));
+            if glow {
+                let light = Box::new(DiffuseLight::new(Color::white(), 4.));
+                scene.add(Glow(Sphere::at(0., 4., -3., 2., light)));
+            }
+            Arc::new(scene)
+        };
+        let brightness =
+            |colors: &ColorBuffer| -> f64 { (0..8).map(|x| colors.get(x, 6).r()).sum() };
+
+        let without = brightness(&render(&cam, &image, &config, &scene(false)).unwrap());
+        let with = brightness(&render(&cam, &image, &config, &scene(true)).unwrap());
+        assert_eq!(scene(true).lights().len(), 1);
+        assert!(with > without + 0.1, "{} vs {}", with, without);
+    }
+
+    #[test]
+    fn render_returns_linear_colors() {
+        let (image, cam) = test_view(8, 2.);
+        let mut config = test_config();
+        config.background = Arc::new(|_| Color::new(2., 0.5, 0.));
+        let colors = render(&cam, &image, &config, &Arc::new(Scene::new())).unwrap();
+
+        assert_eq!(
+            (colors.width(), colors.height()),
+            (image.width(), image.height())
+        );
+        assert!(colors.get(3, 2).approx_eq(&Color::new(2., 0.5, 0.), 1e-12));
+        assert_eq!(colors.samples(3, 2), config.samples as u32);
+    }
+
+    #[test]
+    fn progress_is_reported_after_every_line() {
+        let (image, cam) = test_view(8, 2.);
+        let fractions = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut config = test_config();
+        let reported = fractions.clone();
+        config.progress = Some(Arc::new(move |progress: Progress| {
+            reported.lock().unwrap().push(progress.fraction)
+        }));
+        calculate_image(&cam, &image, &config, &Arc::new(Scene::new())).unwrap();
+
+        let fractions = fractions.lock().unwrap();
+        assert_eq!(fractions.len(), image.height() as usize);
+        assert!(fractions.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(fractions.last(), Some(&1.));
+    }
+
+    #[test]
+    fn every_line_is_passed_to_the_tile_callback() {
+        let (image, cam) = test_view(8, 2.);
+        let tiles = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut config = test_config();
+        let received = tiles.clone();
+        config.on_tile = Some(Arc::new(move |tile: Tile<'_>| {
+            received.lock().unwrap().push((
+                tile.x,
+                tile.y,
+                tile.width,
+                tile.height,
+                tile.pixels.to_vec(),
+            ))
+        }));
+        let (buffer, _) = calculate_image(&cam, &image, &config, &Arc::new(Scene::new())).unwrap();
+
+        let mut tiles = tiles.lock().unwrap();
+        tiles.sort_by_key(|tile| tile.1);
+        assert_eq!(tiles.len(), image.height() as usize);
+        for (y, (x, tile_y, width, height, pixels)) in tiles.iter().enumerate() {
+            assert_eq!(
+                (*x, *tile_y, *width, *height),
+                (0, y as u32, image.width(), 1)
+            );
+            let row = 4 * image.width() as usize;
+            assert_eq!(pixels[..], buffer.as_raw()[y * row..(y + 1) * row]);
+        }
+    }
+
+    #[test]
+    fn sky_is_at_the_top_of_the_image() {
+        let buffer = render_sky(false);
+        let bottom = buffer.height() - 1;
+        assert_eq!(buffer.get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+        assert_eq!(buffer.get_pixel(15, 0), &Rgba([255, 0, 0, 255]));
+        assert_eq!(buffer.get_pixel(0, bottom), &Rgba([0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn flip_y_puts_sky_at_the_bottom_of_the_image() {
+        let buffer = render_sky(true);
+        let bottom = buffer.height() - 1;
+        assert_eq!(buffer.get_pixel(0, 0), &Rgba([0, 0, 0, 255]));
+        assert_eq!(buffer.get_pixel(0, bottom), &Rgba([255, 0, 0, 255]));
+        assert_eq!(buffer.get_pixel(15, bottom), &Rgba([255, 0, 0, 255]));
+    }
+}
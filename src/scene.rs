@@ -0,0 +1,133 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use crate::aabb::Aabb;
+use crate::hit_record::HitRecord;
+use crate::ray::Ray;
+use crate::TraceableObjects;
+
+/// Objects of the rendered scene. The scene is built once and can be shared (e.g. in an `Arc`) by
+/// any number of renders, it is not modified by them.
+#[derive(Default)]
+pub struct Scene {
+    /// All objects in the scene (including lights).
+    objects: Vec<Arc<dyn TraceableObjects>>,
+    /// Emissive objects of the scene which can be sampled (see `Hittable::can_be_sampled`), kept
+    /// separately so that they can be sampled by shadow rays without scanning the whole scene.
+    lights: Vec<Arc<dyn TraceableObjects>>,
+}
+
+impl Scene {
+    pub fn new() -> Scene {
+        Scene::default()
+    }
+
+    /// Adds the object to the scene, emissive objects which can be sampled are registered as
+    /// lights too. Degenerate objects are skipped with a warning.
+    pub fn add_object(&mut self, object: Arc<dyn TraceableObjects>) {
+        if object.is_degenerate() {
+            log::warn!(
+                "Object {} of the scene is degenerate (it cannot be hit by any ray), skipping it",
+                self.objects.len()
+            );
+            return;
+        }
+        if object.is_emissive() && object.can_be_sampled() {
+            self.lights.push(object.clone());
+        }
+        self.objects.push(object);
+    }
+
+    /// Same as `add_object`, the object is wrapped into an `Arc` here.
+    pub fn add<T: TraceableObjects + 'static>(&mut self, object: T) {
+        self.add_object(Arc::new(object));
+    }
+
+    /// Number of objects in the scene (lights included).
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    /// Iterates over all objects of the scene in the order they were added (degenerate objects
+    /// are not in the scene).
+    pub fn iter(&self) -> core::slice::Iter<'_, Arc<dyn TraceableObjects>> {
+        self.objects.iter()
+    }
+
+    /// Returns the box containing all objects of the scene, e.g. for framing the camera. `None` if
+    /// the scene is empty or any of its objects is not bounded.
+    pub fn bounding_box(&self) -> Option<Aabb> {
+        let mut boxes = self.objects.iter().map(|object| object.bounding_box());
+        let first = boxes.next()??;
+        boxes.try_fold(first, |bbox, other| Some(bbox.surrounding(&other?)))
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn objects(&self) -> &[Arc<dyn TraceableObjects>] {
+        &self.objects
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn lights(&self) -> &[Arc<dyn TraceableObjects>] {
+        &self.lights
+    }
+
+    /// Returns `true` if the ray hits any object on interval (t_min, t_max). Unlike the search for
+    /// the nearest hit, it stops at the first object found, which is all that shadow rays need.
+    pub fn hit_any(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.hit_any_counted(ray, t_min, t_max).0
+    }
+
+    /// Same as `hit_any`, also returns the number of intersection tests it took (for ray
+    /// statistics of the render).
+    pub(crate) fn hit_any_counted(&self, ray: &Ray, t_min: f64, t_max: f64) -> (bool, u64) {
+        let mut rec = HitRecord::new();
+        let mut tests = 0;
+        let hit = self.objects.iter().any(|s| {
+            tests += 1;
+            s.hit(ray, t_min, t_max, &mut rec)
+        });
+        (hit, tests)
+    }
+}
+
+impl<'a> IntoIterator for &'a Scene {
+    type Item = &'a Arc<dyn TraceableObjects>;
+    type IntoIter = core::slice::Iter<'a, Arc<dyn TraceableObjects>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::{DiffuseLight, Lambertian};
+    use crate::objects::Sphere;
+
+    #[test]
+    fn degenerate_objects_are_not_added() {
+        let mut scene = Scene::new();
+        let gray = Color::new(0.5, 0.5, 0.5);
+        scene.add(Sphere::at(
+            0.,
+            0.,
+            0.,
+            0.,
+            Box::new(Lambertian::new(gray.copy())),
+        ));
+        let light = Box::new(DiffuseLight::new(Color::white(), 1.));
+        scene.add(Sphere::at(0., 0., 0., -1., light));
+        assert!(scene.is_empty());
+        assert!(scene.lights().is_empty());
+
+        scene.add(Sphere::at(0., 0., 0., 1., Box::new(Lambertian::new(gray))));
+        assert_eq!(scene.len(), 1);
+    }
+}
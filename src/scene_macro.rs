@@ -0,0 +1,173 @@
+use crate::camera::PerspectiveCamera;
+use crate::scene::Scene;
+
+/// Builds a `Scene` from a list of objects, e.g.
+/// `scene! { sphere(0, 0, -1; 0.5; lambertian(0.8, 0.2, 0.2)); }` is a scene with a red sphere.
+///
+/// Every object ends with `;`, its parameters are separated by `;` and numbers can be integers:
+/// * `sphere(x, y, z; radius; material)`,
+/// * `capsule(x, y, z; x, y, z; radius; material)` - centers of the caps and the radius.
+///
+/// Materials are given by RGB components of their color:
+/// * `lambertian(r, g, b)`,
+/// * `metal(r, g, b)` for a shiny metal and `metal(r, g, b; fuzz)` for a fuzzy one,
+/// * `light(r, g, b; intensity)` for a diffuse light.
+///
+/// Objects are added by `Scene::add`, so degenerate ones are skipped.
+///
+/// The list may contain a camera, `camera(x, y, z; x, y, z; vfov; aspect_ratio)` is a
+/// `PerspectiveCamera` at the first point looking at the second one with the vertical field of view
+/// `vfov` in degrees (the up direction is +Y). The macro then returns a tuple of the scene and the
+/// camera, e.g. `let (scene, camera) = scene! { camera(0, 0, 1; 0, 0, -1; 40; 1.5); ... };`. If
+/// there are more cameras, the last one is used.
+#[macro_export]
+macro_rules! scene {
+    ($($entry:ident ( $($arguments:tt)* );)*) => {{
+        // The scene is not modified if it has no objects
+        #[allow(unused_mut)]
+        let mut scene = $crate::scene::Scene::new();
+        // Replaced by the camera of the list (if any), which changes the type of the result
+        #[allow(unused_variables)]
+        let camera = ();
+        $($crate::__scene_entry!(scene, camera; $entry($($arguments)*));)*
+        $crate::__SceneOutput::finish(camera, scene)
+    }};
+}
+
+/// Result of `scene!` for the camera given by the list, `()` stands for no camera.
+pub trait SceneOutput {
+    type Output;
+
+    fn finish(self, scene: Scene) -> Self::Output;
+}
+
+impl SceneOutput for () {
+    type Output = Scene;
+
+    fn finish(self, scene: Scene) -> Scene {
+        scene
+    }
+}
+
+impl SceneOutput for PerspectiveCamera {
+    type Output = (Scene, PerspectiveCamera);
+
+    fn finish(self, scene: Scene) -> (Scene, PerspectiveCamera) {
+        (scene, self)
+    }
+}
+
+/// Adds an object to the scene, or declares the camera (the binding is visible to the rest of
+/// `scene!`, because the macro expands in statement position).
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __scene_entry {
+    ($scene:ident, $camera:ident; camera(
+        $x1:expr, $y1:expr, $z1:expr;
+        $x2:expr, $y2:expr, $z2:expr;
+        $vfov:expr;
+        $aspect_ratio:expr
+    )) => {
+        let $camera = $crate::camera::PerspectiveCamera::look_at(
+            $crate::vec3::Vec3::new($x1 as f64, $y1 as f64, $z1 as f64),
+            $crate::vec3::Vec3::new($x2 as f64, $y2 as f64, $z2 as f64),
+            $crate::vec3::Vec3::new(0., 1., 0.),
+            $vfov as f64,
+            $aspect_ratio as f64,
+        );
+    };
+    ($scene:ident, $camera:ident; $object:ident ( $($arguments:tt)* )) => {
+        $scene.add($crate::__scene_object!($object($($arguments)*)));
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __scene_object {
+    (sphere($x:expr, $y:expr, $z:expr; $radius:expr; $material:ident ( $($arguments:tt)* ))) => {
+        $crate::objects::Sphere::at(
+            $x as f64,
+            $y as f64,
+            $z as f64,
+            $radius as f64,
+            $crate::__scene_material!($material($($arguments)*)),
+        )
+    };
+    (capsule(
+        $x1:expr, $y1:expr, $z1:expr;
+        $x2:expr, $y2:expr, $z2:expr;
+        $radius:expr;
+        $material:ident ( $($arguments:tt)* )
+    )) => {
+        $crate::objects::Capsule::new(
+            $crate::vec3::Vec3::new($x1 as f64, $y1 as f64, $z1 as f64),
+            $crate::vec3::Vec3::new($x2 as f64, $y2 as f64, $z2 as f64),
+            $radius as f64,
+            $crate::__scene_material!($material($($arguments)*)),
+        )
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __scene_material {
+    (lambertian($r:expr, $g:expr, $b:expr)) => {
+        $crate::__SceneBox::new($crate::material::Lambertian::new($crate::__scene_color!(
+            $r, $g, $b
+        )))
+    };
+    (metal($r:expr, $g:expr, $b:expr)) => {
+        $crate::__SceneBox::new($crate::material::Metal::shiny($crate::__scene_color!(
+            $r, $g, $b
+        )))
+    };
+    (metal($r:expr, $g:expr, $b:expr; $fuzz:expr)) => {
+        $crate::__SceneBox::new($crate::material::Metal::fuzzy(
+            $crate::__scene_color!($r, $g, $b),
+            $fuzz as f64,
+        ))
+    };
+    (light($r:expr, $g:expr, $b:expr; $intensity:expr)) => {
+        $crate::__SceneBox::new($crate::material::DiffuseLight::new(
+            $crate::__scene_color!($r, $g, $b),
+            $intensity as f64,
+        ))
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __scene_color {
+    ($r:expr, $g:expr, $b:expr) => {
+        $crate::color::Color::new($r as f64, $g as f64, $b as f64)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::camera::Camera;
+
+    #[test]
+    fn scene_without_camera_is_returned_alone() {
+        let scene = scene! {
+            sphere(0, 0, -1; 0.5; lambertian(0.8, 0.2, 0.2));
+            capsule(0, 0, -2; 1, 0, -2; 0.25; metal(0.9, 0.9, 0.9; 0.1));
+            sphere(0, 0, 0; 0; light(1, 1, 1; 4));
+        };
+        // The sphere with zero radius is degenerate and skipped
+        assert_eq!(scene.len(), 2);
+    }
+
+    #[test]
+    fn camera_is_returned_with_the_scene() {
+        let (scene, camera) = scene! {
+            camera(0, 0, 1; 0, 0, -1; 40; 1.5);
+            sphere(0, 0, -1; 0.5; light(1, 1, 1; 4));
+        };
+        assert_eq!(scene.len(), 1);
+        assert!((camera.aspect_ratio() - 1.5).abs() < 1e-12);
+        // The center of the picture looks at the sphere
+        let direction = camera.calculate_ray(0.5, 0.5).direction().unit();
+        assert!(direction.approx_eq(&crate::vec3::Vec3::new(0., 0., -1.), 1e-12));
+    }
+}
@@ -0,0 +1,103 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::hit_record::HitRecord;
+use crate::math;
+use crate::objects::Sphere;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+use crate::Hittable;
+
+/// Number of spheres tested together, 4 `f64`s fill a 256-bit vector register.
+const LANES: usize = 4;
+
+/// Geometry of many spheres stored by components (structure of arrays), so that the intersection
+/// test runs on `LANES` spheres at once. The loops over the lanes have no branches, which lets the
+/// compiler turn them into SIMD instructions.
+///
+/// Materials are not stored, the hit is finished by the scalar `Sphere::hit` of the nearest sphere.
+pub struct SphereBatch {
+    x: Vec<[f64; LANES]>,
+    y: Vec<[f64; LANES]>,
+    z: Vec<[f64; LANES]>,
+    radius_squared: Vec<[f64; LANES]>,
+}
+
+impl SphereBatch {
+    pub fn new(spheres: &[Sphere]) -> SphereBatch {
+        let chunks = spheres.len().div_ceil(LANES);
+        // Unused lanes of the last chunk have infinitely negative squared radius, which gives
+        // a negative discriminant for every ray
+        let mut batch = SphereBatch {
+            x: vec![[0.; LANES]; chunks],
+            y: vec![[0.; LANES]; chunks],
+            z: vec![[0.; LANES]; chunks],
+            radius_squared: vec![[f64::NEG_INFINITY; LANES]; chunks],
+        };
+        for (i, sphere) in spheres.iter().enumerate() {
+            let (chunk, lane) = (i / LANES, i % LANES);
+            let center = sphere.center();
+            batch.x[chunk][lane] = center.x();
+            batch.y[chunk][lane] = center.y();
+            batch.z[chunk][lane] = center.z();
+            batch.radius_squared[chunk][lane] = sphere.radius() * sphere.radius();
+        }
+        batch
+    }
+
+    /// Returns index of the sphere with the nearest intersection on interval (t_min, t_max)
+    /// together with its parameter `t`. The roots are computed the same way as by `Sphere::hit`.
+    pub fn nearest(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(usize, f64)> {
+        let (origin, direction) = (ray.origin(), ray.direction());
+        let a = Vec3::dot(direction, direction);
+        let mut nearest = None;
+        let mut closest_t = t_max;
+        for chunk in 0..self.x.len() {
+            let mut roots = [f64::INFINITY; LANES];
+            for (lane, root) in roots.iter_mut().enumerate() {
+                let ox = origin.x() - self.x[chunk][lane];
+                let oy = origin.y() - self.y[chunk][lane];
+                let oz = origin.z() - self.z[chunk][lane];
+                let b = 2.0 * (direction.x() * ox + direction.y() * oy + direction.z() * oz);
+                let c = ox * ox + oy * oy + oz * oz - self.radius_squared[chunk][lane];
+                let discriminant = b * b - 4. * a * c;
+                let sqrt_discriminant = math::sqrt(discriminant.max(0.));
+                let near = (-b - sqrt_discriminant) / (2.0 * a);
+                let far = (-b + sqrt_discriminant) / (2.0 * a);
+                let in_range = |t: f64| t_min <= t && t <= closest_t;
+                *root = if discriminant <= 0.0 {
+                    f64::INFINITY
+                } else if in_range(near) {
+                    near
+                } else if in_range(far) {
+                    far
+                } else {
+                    f64::INFINITY
+                };
+            }
+            // Roots in the range are at most `closest_t`, lanes after the first hit of the chunk
+            // have to be nearer
+            for (lane, &root) in roots.iter().enumerate() {
+                if root.is_finite() && (nearest.is_none() || root < closest_t) {
+                    closest_t = root;
+                    nearest = Some((chunk * LANES + lane, root));
+                }
+            }
+        }
+        nearest
+    }
+
+    /// Finds the nearest of the `spheres` (the ones the batch was created from) and fills `rec`
+    /// by its `Sphere::hit`. Returns index of the sphere, `None` if the ray misses all of them.
+    pub fn hit(
+        &self,
+        spheres: &[Sphere],
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rec: &mut HitRecord,
+    ) -> Option<usize> {
+        let (index, _) = self.nearest(ray, t_min, t_max)?;
+        spheres[index].hit(ray, t_min, t_max, rec).then_some(index)
+    }
+}
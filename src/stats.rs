@@ -0,0 +1,119 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Ray statistics are collected only with the `ray-stats` feature, so that release renders do not
+/// pay for the shared counters.
+const ENABLED: bool = cfg!(feature = "ray-stats");
+
+/// Statistics of one render, shared by all its worker threads. Every render has its own, so that
+/// renders running at the same time do not mix their counts.
+#[derive(Default)]
+pub(crate) struct RayStats {
+    /// Number of traced rays (camera, scattered and shadow rays)
+    rays: AtomicU64,
+    /// Number of ray-object intersection tests
+    intersection_tests: AtomicU64,
+
+    /// Number of paths traced from the camera
+    paths: AtomicU64,
+    /// Sum of bounces of all camera paths
+    bounces: AtomicU64,
+    max_bounces: AtomicU64,
+    /// Number of camera paths terminated by the depth limit (instead of escaping or being absorbed)
+    clipped_paths: AtomicU64,
+
+    /// Number of rays with non-finite origin or direction, they are counted even without the
+    /// `ray-stats` feature (they are rare and indicate a bug in a material or an object)
+    invalid_rays: AtomicU64,
+}
+
+impl RayStats {
+    pub(crate) fn new() -> RayStats {
+        RayStats::default()
+    }
+
+    /// Records one traversal of the scene by a ray, which needed `tests` intersection tests.
+    pub(crate) fn record_traversal(&self, tests: u64) {
+        if ENABLED {
+            self.rays.fetch_add(1, Ordering::Relaxed);
+            self.intersection_tests.fetch_add(tests, Ordering::Relaxed);
+        }
+    }
+
+    /// Records a finished camera path, which took `bounces` bounces. `clipped` is `true` if the
+    /// path was terminated by the depth limit.
+    pub(crate) fn record_path(&self, bounces: u16, clipped: bool) {
+        if ENABLED {
+            self.paths.fetch_add(1, Ordering::Relaxed);
+            self.bounces.fetch_add(bounces as u64, Ordering::Relaxed);
+            self.max_bounces
+                .fetch_max(bounces as u64, Ordering::Relaxed);
+            if clipped {
+                self.clipped_paths.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Records a ray that was not traced because it has non-finite components.
+    pub(crate) fn record_invalid_ray(&self) {
+        self.invalid_rays.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Logs the collected statistics.
+    pub(crate) fn report(&self) {
+        let invalid_rays = self.invalid_rays.load(Ordering::Relaxed);
+        if invalid_rays > 0 {
+            log::warn!(
+                "{} rays with NaN or infinite components were not traced (they bring no light)",
+                invalid_rays
+            );
+        }
+        if !ENABLED {
+            return;
+        }
+        let rays = self.rays.load(Ordering::Relaxed);
+        let tests = self.intersection_tests.load(Ordering::Relaxed);
+        log::info!(
+            "Traced {} rays, {:.2} intersection tests per ray on average",
+            rays,
+            tests as f64 / rays.max(1) as f64
+        );
+        let paths = self.paths.load(Ordering::Relaxed);
+        log::info!(
+            "Traced {} camera paths, {:.2} bounces on average, at most {}, {} paths cut off by \
+             the depth limit",
+            paths,
+            self.bounces.load(Ordering::Relaxed) as f64 / paths.max(1) as f64,
+            self.max_bounces.load(Ordering::Relaxed),
+            self.clipped_paths.load(Ordering::Relaxed)
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_count_separately() {
+        let first = RayStats::new();
+        let second = RayStats::new();
+        first.record_invalid_ray();
+        first.record_traversal(3);
+        second.record_traversal(1);
+        first.record_path(3, false);
+        first.record_path(5, true);
+        second.record_path(1, false);
+
+        assert_eq!(first.invalid_rays.load(Ordering::Relaxed), 1);
+        assert_eq!(second.invalid_rays.load(Ordering::Relaxed), 0);
+        if ENABLED {
+            assert_eq!(first.intersection_tests.load(Ordering::Relaxed), 3);
+            assert_eq!(second.intersection_tests.load(Ordering::Relaxed), 1);
+            assert_eq!(first.paths.load(Ordering::Relaxed), 2);
+            assert_eq!(first.max_bounces.load(Ordering::Relaxed), 5);
+            assert_eq!(first.clipped_paths.load(Ordering::Relaxed), 1);
+            assert_eq!(second.paths.load(Ordering::Relaxed), 1);
+            assert_eq!(second.max_bounces.load(Ordering::Relaxed), 1);
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use alloc::sync::Arc;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+#[cfg(feature = "std")]
+use std::fs::File;
+#[cfg(feature = "std")]
+use std::io::BufReader;
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
+#[cfg(feature = "std")]
+use std::sync::Mutex;
+
+#[cfg(feature = "std")]
+use image::codecs::hdr::HdrDecoder;
+#[cfg(feature = "std")]
+use image::ImageResult;
+
+use crate::color::Color;
+use crate::math;
+use crate::vec3::Vec3 as Point;
+
+/// Describes a color that varies over the surface of an object.
+pub trait Texture: Send + Sync {
+    /// Returns linear color at texture coordinates `u`, `v` (both from 0 to 1) of the hit `point`.
+    fn value(&self, u: f64, v: f64, point: Point) -> Color;
+}
+
+/// Shared textures (e.g. from `TextureCache`) can be used wherever a texture is expected.
+impl<T: Texture + ?Sized> Texture for Arc<T> {
+    fn value(&self, u: f64, v: f64, point: Point) -> Color {
+        (**self).value(u, v, point)
+    }
+}
+
+/// The same color everywhere.
+pub struct SolidColor {
+    color: Color,
+}
+
+impl SolidColor {
+    pub fn new(color: Color) -> SolidColor {
+        SolidColor { color }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _point: Point) -> Color {
+        self.color.copy()
+    }
+}
+
+/// Coordinate along which `GradientTexture` changes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GradientAxis {
+    /// Coordinates of the hit point in the world space
+    X,
+    Y,
+    Z,
+    /// Texture coordinates of the hit point
+    U,
+    V,
+}
+
+/// Linear blend of two colors along one coordinate, e.g. a vertical gradient over an object.
+pub struct GradientTexture {
+    start: Color,
+    end: Color,
+    axis: GradientAxis,
+    from: f64,
+    to: f64,
+}
+
+impl GradientTexture {
+    /// The color is `start` where the `axis` coordinate is `from` and `end` where it is `to`,
+    /// values beyond them keep the color of the nearer one. Returns `None` if `from` and `to` are
+    /// equal or not finite.
+    pub fn new(
+        start: Color,
+        end: Color,
+        axis: GradientAxis,
+        from: f64,
+        to: f64,
+    ) -> Option<GradientTexture> {
+        if from == to || !from.is_finite() || !to.is_finite() {
+            return None;
+        }
+        Some(GradientTexture {
+            start,
+            end,
+            axis,
+            from,
+            to,
+        })
+    }
+}
+
+impl Texture for GradientTexture {
+    fn value(&self, u: f64, v: f64, point: Point) -> Color {
+        let coordinate = match self.axis {
+            GradientAxis::X => point.x(),
+            GradientAxis::Y => point.y(),
+            GradientAxis::Z => point.z(),
+            GradientAxis::U => u,
+            GradientAxis::V => v,
+        };
+        let t = ((coordinate - self.from) / (self.to - self.from)).clamp(0., 1.);
+        Color::lerp(&self.start, &self.end, t)
+    }
+}
+
+/// Describes how values stored in an image file relate to the linear light intensities that are
+/// used by the lighting math.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    /// Values are encoded by the sRGB transfer function, which is the case of most 8-bit images
+    /// with colors (photos, painted textures).
+    Srgb,
+    /// Values are stored as they are, e.g. data textures (normal or roughness maps).
+    Linear,
+}
+
+impl ColorSpace {
+    /// Converts the stored `value` (from 0 to 1) to linear intensity.
+    pub fn to_linear(self, value: f64) -> f64 {
+        match self {
+            ColorSpace::Srgb if value <= 0.04045 => value / 12.92,
+            ColorSpace::Srgb => math::powf((value + 0.055) / 1.055, 2.4),
+            ColorSpace::Linear => value,
+        }
+    }
+}
+
+/// Texture given by an image, which is mapped onto the surface using the texture coordinates
+/// (nearest pixel is used, no filtering). Colors are converted to linear values on load.
+#[cfg(feature = "std")]
+pub struct ImageTexture {
+    width: u32,
+    height: u32,
+    /// Linear colors of the pixels, row by row from the top of the image
+    pixels: Vec<Color>,
+}
+
+#[cfg(feature = "std")]
+impl ImageTexture {
+    /// Loads an 8-bit image (PNG, JPEG, ...), whose values are encoded in `color_space`.
+    pub fn open<P: AsRef<Path>>(path: P, color_space: ColorSpace) -> ImageResult<ImageTexture> {
+        let image = image::open(path)?.to_rgb8();
+        let decode = |c: u8| color_space.to_linear(c as f64 / 255.);
+        Ok(ImageTexture {
+            width: image.width(),
+            height: image.height(),
+            pixels: image
+                .pixels()
+                .map(|p| Color::new(decode(p[0]), decode(p[1]), decode(p[2])))
+                .collect(),
+        })
+    }
+
+    /// Loads a Radiance HDR (`.hdr`) image, e.g. an environment map. HDR images already hold
+    /// linear values (not limited to 1), which are kept as they are.
+    pub fn open_hdr<P: AsRef<Path>>(path: P) -> ImageResult<ImageTexture> {
+        let decoder = HdrDecoder::new(BufReader::new(File::open(path)?))?;
+        let metadata = decoder.metadata();
+        Ok(ImageTexture {
+            width: metadata.width,
+            height: metadata.height,
+            pixels: decoder
+                .read_image_hdr()?
+                .iter()
+                .map(|p| Color::new(p[0] as f64, p[1] as f64, p[2] as f64))
+                .collect(),
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+}
+
+#[cfg(feature = "std")]
+impl Texture for ImageTexture {
+    /// `v` goes from the bottom of the image (0) to its top (1), coordinates out of range are
+    /// clamped to the edge of the image.
+    fn value(&self, u: f64, v: f64, _point: Point) -> Color {
+        let u = u.clamp(0., 1.);
+        let v = 1. - v.clamp(0., 1.);
+        let i = ((u * self.width as f64) as u32).min(self.width - 1);
+        let j = ((v * self.height as f64) as u32).min(self.height - 1);
+        self.pixels[(j * self.width + i) as usize].copy()
+    }
+}
+
+/// Path of the image and its color space, HDR images have no color space.
+#[cfg(feature = "std")]
+type CacheKey = (PathBuf, Option<ColorSpace>);
+
+/// Image textures loaded so far, so that objects using the same image file share one copy of it.
+/// The cache can be shared by multiple threads.
+#[cfg(feature = "std")]
+#[derive(Default)]
+pub struct TextureCache {
+    textures: Mutex<HashMap<CacheKey, Arc<ImageTexture>>>,
+}
+
+#[cfg(feature = "std")]
+impl TextureCache {
+    pub fn new() -> TextureCache {
+        TextureCache::default()
+    }
+
+    /// Returns the texture loaded by `ImageTexture::open`, the file is loaded only the first time.
+    pub fn open<P: AsRef<Path>>(
+        &self,
+        path: P,
+        color_space: ColorSpace,
+    ) -> ImageResult<Arc<ImageTexture>> {
+        self.get_or_load(path.as_ref(), Some(color_space), |path| {
+            ImageTexture::open(path, color_space)
+        })
+    }
+
+    /// Returns the texture loaded by `ImageTexture::open_hdr`, the file is loaded only the first
+    /// time.
+    pub fn open_hdr<P: AsRef<Path>>(&self, path: P) -> ImageResult<Arc<ImageTexture>> {
+        self.get_or_load(path.as_ref(), None, |path| ImageTexture::open_hdr(path))
+    }
+
+    fn get_or_load<F>(
+        &self,
+        path: &Path,
+        color_space: Option<ColorSpace>,
+        load: F,
+    ) -> ImageResult<Arc<ImageTexture>>
+    where
+        F: FnOnce(&Path) -> ImageResult<ImageTexture>,
+    {
+        // The lock is held while loading, so that the same file is not loaded by two threads
+        let mut textures = self.textures.lock().unwrap();
+        let key = (path.to_path_buf(), color_space);
+        if let Some(texture) = textures.get(&key) {
+            return Ok(texture.clone());
+        }
+        let texture = Arc::new(load(path)?);
+        textures.insert(key, texture.clone());
+        Ok(texture)
+    }
+}
@@ -1,11 +1,10 @@
 /// This file is taken from (based on) Rust book. Its repository can be found here:
 /// https://github.com/rust-lang/book
-
 use std::error::Error;
 use std::fmt::Formatter;
 use std::sync::{mpsc, Arc, Mutex};
 use std::thread::JoinHandle;
-use std::{fmt, thread};
+use std::{fmt, io, thread};
 
 type Job = Box<dyn FnOnce() + Send + 'static>;
 
@@ -23,54 +22,59 @@ struct Worker {
 impl Worker {
     /// Creates a new thread that waits for jobs wrapped in `Message` and then
     /// executes them. It stops when it gets `Message::Terminate`. All messages
-    /// are transferred through the channel.
-    fn new(id: u8, receiver: Arc<Mutex<mpsc::Receiver<Message>>>) -> Self {
+    /// are transferred through the channel. Fails if the thread cannot be
+    /// spawned.
+    fn new(id: u8, receiver: Arc<Mutex<mpsc::Receiver<Message>>>) -> io::Result<Self> {
         // The closure *can outlive* the function `new`, so it has to take
         // ownership of `receiver`.
-        let thread = thread::spawn(move || {
-            log::info!("Thread spawned");
-            loop {
-                // The lock is not assigned to a variable and therefore is released
-                // as soon as the `let job` statement ends (the lock is held during
-                // the call to recv)
-                let message = receiver
-                    .lock()
-                    .expect(
-                        "Mutex is probably in a poisoned state (some thread 
+        let thread = thread::Builder::new()
+            .name(format!("worker-{}", id))
+            .spawn(move || {
+                log::info!("Thread spawned");
+                loop {
+                    // The lock is not assigned to a variable and therefore is released
+                    // as soon as the `let job` statement ends (the lock is held during
+                    // the call to recv)
+                    let message = receiver
+                        .lock()
+                        .expect(
+                            "Mutex is probably in a poisoned state (some thread 
                         panicked while holding the lock) and therefore this thread
                         cannot get access to the Mutex.",
-                    )
-                    .recv()
-                    .expect("The sending side of the channel has probably shut down.");
-
-                match message {
-                    Message::NewJob(job) => {
-                        log::debug!("Worker {} got a job; executing.", id);
-                        job(); // calling closure
-                    }
-                    Message::Terminate => {
-                        log::debug!("Worker {} was told to terminate.", id);
-                        break;
+                        )
+                        .recv()
+                        .expect("The sending side of the channel has probably shut down.");
+
+                    match message {
+                        Message::NewJob(job) => {
+                            log::debug!("Worker {} got a job; executing.", id);
+                            job(); // calling closure
+                        }
+                        Message::Terminate => {
+                            log::debug!("Worker {} was told to terminate.", id);
+                            break;
+                        }
                     }
                 }
-            }
-        });
+            })?;
 
-        Self {
+        Ok(Self {
             id,
             thread: Some(thread),
-        }
+        })
     }
 }
 
+/// Fixed number of named worker threads executing the closures passed to `execute`.
 pub struct ThreadPool {
     workers: Vec<Worker>,
     sender: mpsc::Sender<Message>,
 }
 
 impl ThreadPool {
-    /// * `size` is the number of threads in the pool. If the size is 0,
-    /// function returns a custom error `PoolCreationError`.
+    /// * `size` is the number of threads in the pool. If the size is 0, or
+    ///   the threads cannot be spawned (e.g. on platforms without threads),
+    ///   function returns a custom error `PoolCreationError`.
     pub fn new(size: u8) -> Result<ThreadPool, PoolCreationError> {
         if size > 0 {
             log::info!("Started creating a thread pool");
@@ -80,15 +84,32 @@ impl ThreadPool {
             // More efficient than Vec::new()
             let mut workers = Vec::with_capacity(size as usize);
             for id in 0..size {
-                workers.push(Worker::new(id, Arc::clone(&receiver)));
+                // Workers spawned so far are terminated when the pool is dropped
+                match Worker::new(id, Arc::clone(&receiver)) {
+                    Ok(worker) => workers.push(worker),
+                    Err(e) => {
+                        drop(ThreadPool { workers, sender });
+                        return Err(PoolCreationError::Spawn(e));
+                    }
+                }
             }
 
             Ok(ThreadPool { workers, sender })
         } else {
-            Err(PoolCreationError)
+            Err(PoolCreationError::ZeroThreads)
         }
     }
 
+    /// Number of worker threads.
+    pub fn len(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Returns `true` if the pool has no worker threads, which `new` never creates.
+    pub fn is_empty(&self) -> bool {
+        self.workers.is_empty()
+    }
+
     /// Thread pool executes the closure.
     /// * `Send` to transfer the closure from one thread to another.
     /// * `'static` because we don’t know how long will it take to execute it.
@@ -121,11 +142,49 @@ impl Drop for ThreadPool {
     }
 }
 
+/// Reason why `ThreadPool::new` failed.
 #[derive(Debug)]
-pub struct PoolCreationError;
+pub enum PoolCreationError {
+    /// The pool was asked for zero threads.
+    ZeroThreads,
+    /// A thread could not be spawned.
+    Spawn(io::Error),
+}
 impl Error for PoolCreationError {}
 impl fmt::Display for PoolCreationError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "Cannot create thread pool containing zero threads.")
+        match self {
+            PoolCreationError::ZeroThreads => {
+                write!(f, "Cannot create thread pool containing zero threads.")
+            }
+            PoolCreationError::Spawn(e) => write!(f, "Cannot spawn a thread: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jobs_run_in_named_workers() {
+        let pool = ThreadPool::new(2).unwrap();
+        assert_eq!(pool.len(), 2);
+        let (sender, receiver) = mpsc::channel();
+        pool.execute(move || {
+            sender
+                .send(thread::current().name().map(String::from))
+                .unwrap();
+        });
+        let name = receiver.recv().unwrap().unwrap();
+        assert!(name.starts_with("worker-"));
+    }
+
+    #[test]
+    fn zero_threads_is_an_error() {
+        assert!(matches!(
+            ThreadPool::new(0),
+            Err(PoolCreationError::ZeroThreads)
+        ));
     }
 }
@@ -1,4 +1,5 @@
-use rand::{thread_rng, Rng};
+use crate::math;
+use crate::random::random_range;
 
 /// This struct can be used for 3D Points, Directions, ...
 #[derive(Clone, Copy, Debug)]
@@ -13,12 +14,12 @@ impl Vec3 {
         Vec3 { x, y, z }
     }
 
-    fn length_squared(&self) -> f64 {
+    pub fn length_squared(&self) -> f64 {
         self.x * self.x + self.y * self.y + self.z * self.z
     }
 
     pub fn length(&self) -> f64 {
-        self.length_squared().sqrt()
+        math::sqrt(self.length_squared())
     }
 
     pub fn zero() -> Vec3 {
@@ -45,10 +46,100 @@ impl Vec3 {
         v1.x() * v2.x() + v1.y() * v2.y() + v1.z() * v2.z()
     }
 
+    pub fn cross(v1: Vec3, v2: Vec3) -> Vec3 {
+        Vec3 {
+            x: v1.y * v2.z - v1.z * v2.y,
+            y: v1.z * v2.x - v1.x * v2.z,
+            z: v1.x * v2.y - v1.y * v2.x,
+        }
+    }
+
+    /// Returns vector made of the smaller values of each of the coordinates.
+    pub fn component_min(v1: Vec3, v2: Vec3) -> Vec3 {
+        Vec3 {
+            x: v1.x.min(v2.x),
+            y: v1.y.min(v2.y),
+            z: v1.z.min(v2.z),
+        }
+    }
+
+    /// Returns vector made of the larger values of each of the coordinates.
+    pub fn component_max(v1: Vec3, v2: Vec3) -> Vec3 {
+        Vec3 {
+            x: v1.x.max(v2.x),
+            y: v1.y.max(v2.y),
+            z: v1.z.max(v2.z),
+        }
+    }
+
+    /// Returns vector with absolute values of each of the coordinates.
+    pub fn abs(&self) -> Vec3 {
+        Vec3 {
+            x: self.x.abs(),
+            y: self.y.abs(),
+            z: self.z.abs(),
+        }
+    }
+
     /// Lambertian reflection, drop in replacement for `random_in_unit_sphere`,
     /// with distribution of `cos x`.
+    ///
+    /// Directions are distributed uniformly over the unit sphere. They are generated directly
+    /// from spherical coordinates, the `z` coordinate of uniformly distributed directions is
+    /// uniformly distributed on [-1, 1] (Archimedes' hat-box theorem).
     pub fn random_unit_vector() -> Vec3 {
-        random_in_unit_sphere().unit_vector()
+        let z = random_range(-1., 1.);
+        let r = math::sqrt(1. - z * z);
+        let (sin_phi, cos_phi) = math::sin_cos(2. * core::f64::consts::PI * random_range(0., 1.));
+        Vec3::new(r * cos_phi, r * sin_phi, z)
+    }
+
+    /// Returns a random point uniformly distributed inside of the unit sphere. The volume within
+    /// distance `r` from the center grows with `r³`, therefore the distance is the cube root of a
+    /// uniform random number.
+    pub fn random_in_unit_sphere() -> Vec3 {
+        math::cbrt(random_range(0., 1.)) * &Vec3::random_unit_vector()
+    }
+
+    /// Returns a random unit vector uniformly distributed over the hemisphere around `normal`
+    /// (vectors from the other half of the sphere are flipped).
+    ///
+    /// More thorough explanation can be found at:
+    /// https://raytracing.github.io/books/RayTracingInOneWeekend.html#diffusematerials/analternativediffuseformulation
+    pub fn random_in_hemisphere(normal: Vec3) -> Vec3 {
+        let direction = Vec3::random_unit_vector();
+        if Vec3::dot(direction, normal) > 0. {
+            direction
+        } else {
+            -direction
+        }
+    }
+
+    /// Returns a random unit vector in the hemisphere around `normal` (unit vector) with density
+    /// proportional to the cosine of the angle from the normal (Lambert's law). Points uniformly
+    /// distributed on the unit disk are projected up onto the hemisphere (Malley's method).
+    ///
+    /// More thorough explanation can be found at:
+    /// https://raytracing.github.io/books/RayTracingTheRestOfYourLife.html#generatingrandomdirections/cosinesamplingahemisphere
+    pub fn random_cosine_direction(normal: Vec3) -> Vec3 {
+        let r_squared = random_range(0., 1.);
+        let r = math::sqrt(r_squared);
+        let (sin_phi, cos_phi) = math::sin_cos(2. * core::f64::consts::PI * random_range(0., 1.));
+
+        let (u, v) = orthonormal_basis(normal);
+        math::sqrt(1. - r_squared) * &normal + (r * cos_phi) * &u + (r * sin_phi) * &v
+    }
+
+    /// Returns a random unit vector inside of the cone around `axis` (unit vector). Half-angle of
+    /// the cone is given by its cosine `cos_max`. Directions are distributed uniformly over the
+    /// solid angle of the cone.
+    pub fn random_in_cone(axis: Vec3, cos_max: f64) -> Vec3 {
+        let cos_theta = 1. - random_range(0., 1.) * (1. - cos_max);
+        let sin_theta = math::sqrt(1. - cos_theta * cos_theta);
+        let (sin_phi, cos_phi) = math::sin_cos(2. * core::f64::consts::PI * random_range(0., 1.));
+
+        let (u, v) = orthonormal_basis(axis);
+        cos_theta * &axis + (sin_theta * cos_phi) * &u + (sin_theta * sin_phi) * &v
     }
 
     /// Returns `true` if any of the vector components is near zero.
@@ -57,32 +148,56 @@ impl Vec3 {
         (self.x.abs() < eps) && (self.y.abs() < eps) && (self.z.abs() < eps)
     }
 
-    pub fn unit_vector(&self) -> Vec3 {
-        self / self.length()
+    /// Returns vector of length 1 with the same direction. Zero vector has no direction, therefore
+    /// zero vector is returned for it (instead of dividing by zero).
+    pub fn unit(&self) -> Vec3 {
+        let length = self.length();
+        if length == 0. {
+            Vec3::zero()
+        } else {
+            self / length
+        }
     }
-}
 
-/// Returns a random vector, distribution `cos^3 x`.
-fn random_in_unit_sphere() -> Vec3 {
-    loop {
-        let p = random(-1., 1.);
-        if p.length_squared() < 1. {
-            return p;
-        }
+    /// Normalizes the vector in place (see `unit`) and returns it, so that calls can be chained.
+    pub fn normalize(&mut self) -> &mut Vec3 {
+        *self = self.unit();
+        self
+    }
+
+    /// Returns `true` if no coordinate is NaN or infinite.
+    pub fn is_finite(&self) -> bool {
+        self.x.is_finite() && self.y.is_finite() && self.z.is_finite()
+    }
+
+    /// Returns `true` if all coordinates differ by at most `eps`.
+    pub fn approx_eq(&self, other: &Vec3, eps: f64) -> bool {
+        (self.x - other.x).abs() <= eps
+            && (self.y - other.y).abs() <= eps
+            && (self.z - other.z).abs() <= eps
     }
 }
 
-fn random(min: f64, max: f64) -> Vec3 {
-    let mut rng = thread_rng();
-    Vec3 {
-        x: rng.gen_range(min..max),
-        y: rng.gen_range(min..max),
-        z: rng.gen_range(min..max),
+impl core::fmt::Display for Vec3 {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "({}, {}, {})", self.x, self.y, self.z)
     }
 }
 
+/// Returns two unit vectors, which together with `axis` (unit vector) form an orthonormal basis.
+fn orthonormal_basis(axis: Vec3) -> (Vec3, Vec3) {
+    let helper = if axis.x.abs() > 0.9 {
+        Vec3::new(0., 1., 0.)
+    } else {
+        Vec3::new(1., 0., 0.)
+    };
+    let v = Vec3::cross(axis, helper).unit();
+    let u = Vec3::cross(axis, v);
+    (u, v)
+}
+
 /// Addition of two `&Vec3` structs. Implemented as adding each of the coordinates together.
-impl std::ops::Add for &Vec3 {
+impl core::ops::Add for &Vec3 {
     type Output = Vec3;
 
     fn add(self, rhs: Self) -> Self::Output {
@@ -94,7 +209,7 @@ impl std::ops::Add for &Vec3 {
     }
 }
 
-impl std::ops::Add for Vec3 {
+impl core::ops::Add for Vec3 {
     type Output = Vec3;
 
     fn add(self, rhs: Self) -> Self::Output {
@@ -106,7 +221,7 @@ impl std::ops::Add for Vec3 {
     }
 }
 
-impl std::ops::Sub for Vec3 {
+impl core::ops::Sub for Vec3 {
     type Output = Vec3;
 
     fn sub(self, rhs: Self) -> Vec3 {
@@ -118,7 +233,7 @@ impl std::ops::Sub for Vec3 {
     }
 }
 
-impl std::ops::Sub for &Vec3 {
+impl core::ops::Sub for &Vec3 {
     type Output = Vec3;
 
     fn sub(self, rhs: Self) -> Vec3 {
@@ -131,7 +246,7 @@ impl std::ops::Sub for &Vec3 {
 }
 
 /// Implements the operation f64 * &Vec3.
-impl std::ops::Mul<&Vec3> for f64 {
+impl core::ops::Mul<&Vec3> for f64 {
     type Output = Vec3;
 
     fn mul(self, rhs: &Vec3) -> Self::Output {
@@ -143,7 +258,7 @@ impl std::ops::Mul<&Vec3> for f64 {
     }
 }
 
-impl std::ops::Div<f64> for Vec3 {
+impl core::ops::Div<f64> for Vec3 {
     type Output = Vec3;
 
     fn div(self, rhs: f64) -> Vec3 {
@@ -155,7 +270,7 @@ impl std::ops::Div<f64> for Vec3 {
     }
 }
 
-impl std::ops::Div<f64> for &Vec3 {
+impl core::ops::Div<f64> for &Vec3 {
     type Output = Vec3;
 
     fn div(self, rhs: f64) -> Vec3 {
@@ -168,7 +283,19 @@ impl std::ops::Div<f64> for &Vec3 {
 }
 
 // Makes vector negative
-impl std::ops::Neg for Vec3 {
+impl core::ops::Neg for Vec3 {
+    type Output = Vec3;
+
+    fn neg(self) -> Self::Output {
+        Vec3 {
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
+        }
+    }
+}
+
+impl core::ops::Neg for &Vec3 {
     type Output = Vec3;
 
     fn neg(self) -> Self::Output {
@@ -179,3 +306,181 @@ impl std::ops::Neg for Vec3 {
         }
     }
 }
+
+/// Divides every coordinate by `rhs` in place.
+impl core::ops::DivAssign<f64> for Vec3 {
+    fn div_assign(&mut self, rhs: f64) {
+        self.x /= rhs;
+        self.y /= rhs;
+        self.z /= rhs;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_vec_eq(actual: Vec3, expected: Vec3) {
+        assert!(
+            actual.approx_eq(&expected, 1e-12),
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn component_min_takes_each_coordinate_separately() {
+        let a = Vec3::new(1., -2., 3.);
+        let b = Vec3::new(-1., 2., 3.5);
+        assert_vec_eq(Vec3::component_min(a, b), Vec3::new(-1., -2., 3.));
+        assert_vec_eq(Vec3::component_min(b, a), Vec3::new(-1., -2., 3.));
+    }
+
+    #[test]
+    fn component_max_takes_each_coordinate_separately() {
+        let a = Vec3::new(1., -2., 3.);
+        let b = Vec3::new(-1., 2., 3.5);
+        assert_vec_eq(Vec3::component_max(a, b), Vec3::new(1., 2., 3.5));
+        assert_vec_eq(Vec3::component_max(b, a), Vec3::new(1., 2., 3.5));
+    }
+
+    #[test]
+    fn abs_flips_only_negative_coordinates() {
+        assert_vec_eq(Vec3::new(-1., 0., 2.5).abs(), Vec3::new(1., 0., 2.5));
+        assert_vec_eq(Vec3::new(3., -0., -4.).abs(), Vec3::new(3., 0., 4.));
+    }
+
+    #[test]
+    fn div_assign_divides_every_coordinate() {
+        let mut v = Vec3::new(2., -4., 6.);
+        v /= 2.;
+        assert_vec_eq(v, Vec3::new(1., -2., 3.));
+    }
+
+    #[test]
+    fn negation_of_reference_keeps_the_original() {
+        let v = Vec3::new(1., -2., 0.5);
+        assert_vec_eq(-&v, Vec3::new(-1., 2., -0.5));
+        assert_vec_eq(v, Vec3::new(1., -2., 0.5));
+        assert_vec_eq(-&v, -v);
+    }
+
+    const SAMPLES: usize = 100_000;
+
+    /// Asserts that `count` of `SAMPLES` is the `expected` fraction within a few standard
+    /// deviations.
+    fn assert_fraction(count: usize, expected: f64) {
+        let fraction = count as f64 / SAMPLES as f64;
+        let sigma = (expected * (1. - expected) / SAMPLES as f64).sqrt();
+        assert!(
+            (fraction - expected).abs() < 5. * sigma,
+            "expected fraction {}, got {}",
+            expected,
+            fraction
+        );
+    }
+
+    /// Counts the vectors in each of the eight octants.
+    fn octants(vectors: &[Vec3]) -> [usize; 8] {
+        let mut counts = [0; 8];
+        for v in vectors {
+            let index =
+                (v.x > 0.) as usize | ((v.y > 0.) as usize) << 1 | ((v.z > 0.) as usize) << 2;
+            counts[index] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn random_unit_vector_is_uniform_on_the_sphere() {
+        crate::random::seed(1, 0);
+        let vectors: Vec<Vec3> = (0..SAMPLES).map(|_| Vec3::random_unit_vector()).collect();
+        for v in &vectors {
+            assert!((v.length() - 1.).abs() < 1e-9, "{} is not a unit vector", v);
+        }
+        for count in octants(&vectors).iter() {
+            assert_fraction(*count, 1. / 8.);
+        }
+        // Uniform directions have uniformly distributed coordinates (hat-box theorem)
+        for axis in 0..3 {
+            let coordinate = |v: &Vec3| [v.x, v.y, v.z][axis];
+            for band in 0..4 {
+                let low = -1. + band as f64 / 2.;
+                let count = vectors
+                    .iter()
+                    .filter(|v| (low..low + 0.5).contains(&coordinate(v)))
+                    .count();
+                assert_fraction(count, 1. / 4.);
+            }
+        }
+    }
+
+    #[test]
+    fn random_in_unit_sphere_is_uniform_in_the_ball() {
+        crate::random::seed(1, 1);
+        let points: Vec<Vec3> = (0..SAMPLES)
+            .map(|_| Vec3::random_in_unit_sphere())
+            .collect();
+        for p in &points {
+            assert!(p.length() <= 1., "{} is outside of the unit sphere", p);
+        }
+        for count in octants(&points).iter() {
+            assert_fraction(*count, 1. / 8.);
+        }
+        // The ball of half the radius holds one eighth of the volume
+        let inner = points.iter().filter(|p| p.length() < 0.5).count();
+        assert_fraction(inner, 1. / 8.);
+    }
+
+    /// Counts the directions in the quadrants around `normal`, which is (0, 0, 1) or (0, 0, -1).
+    fn quadrants(directions: &[Vec3]) -> [usize; 4] {
+        let mut counts = [0; 4];
+        for d in directions {
+            counts[(d.x > 0.) as usize | ((d.y > 0.) as usize) << 1] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn random_in_hemisphere_is_uniform_on_the_hemisphere() {
+        crate::random::seed(1, 2);
+        let normal = Vec3::new(0., 0., -1.);
+        let directions: Vec<Vec3> = (0..SAMPLES)
+            .map(|_| Vec3::random_in_hemisphere(normal))
+            .collect();
+        for d in &directions {
+            assert!((d.length() - 1.).abs() < 1e-9 && Vec3::dot(*d, normal) >= 0.);
+        }
+        for count in quadrants(&directions).iter() {
+            assert_fraction(*count, 1. / 4.);
+        }
+        // The cosine of uniformly distributed directions is uniform on [0, 1]
+        let low = directions
+            .iter()
+            .filter(|d| Vec3::dot(**d, normal) < 0.5)
+            .count();
+        assert_fraction(low, 1. / 2.);
+    }
+
+    #[test]
+    fn random_cosine_direction_follows_lamberts_law() {
+        crate::random::seed(1, 3);
+        let normal = Vec3::new(0., 0., 1.);
+        let directions: Vec<Vec3> = (0..SAMPLES)
+            .map(|_| Vec3::random_cosine_direction(normal))
+            .collect();
+        for d in &directions {
+            assert!((d.length() - 1.).abs() < 1e-9 && Vec3::dot(*d, normal) >= 0.);
+        }
+        for count in quadrants(&directions).iter() {
+            assert_fraction(*count, 1. / 4.);
+        }
+        // The squared cosine is uniform on [0, 1] (Malley's method projects a uniform disk)
+        let low = directions
+            .iter()
+            .filter(|d| Vec3::dot(**d, normal) < 0.5)
+            .count();
+        assert_fraction(low, 1. / 4.);
+    }
+}